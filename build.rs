@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Output of a git command, if git is there and it succeeds
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
+
+fn main() {
+    // Embed the git commit for `vim_navigation version`, if built from a checkout
+    let commit = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=VINAV_GIT_COMMIT={}", commit);
+
+    // HEAD only changes when switching branches; a new commit moves the branch
+    // it points to, and every move of HEAD is logged in logs/HEAD. Paths that
+    // don't exist are left out, since cargo would rerun this on every build.
+    let Some(git_dir) = git(&["rev-parse", "--git-dir"]).map(PathBuf::from) else {
+        return;
+    };
+    let mut watched = vec![git_dir.join("HEAD"), git_dir.join("logs").join("HEAD")];
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        watched.push(git_dir.join(branch));
+    }
+    for path in watched.iter().filter(|path| path.exists()) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+}
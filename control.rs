@@ -0,0 +1,276 @@
+//! Wire protocol for the vim_navigation control socket.
+//!
+//! Clients send one JSON request per line and get one JSON response per line,
+//! e.g. `{"cmd":"move_to","x":640,"y":400}` -> `{"ok":true,"message":"Moved to (640, 400)"}`.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A request sent to the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Report mode, cursor position, profile and selection
+    Status,
+    /// Switch to "nav" or "typing" mode
+    SetMode { mode: String },
+    /// Move the cursor to absolute coordinates
+    MoveTo { x: f64, y: f64 },
+    /// Move the cursor relative to where it is
+    MoveBy { dx: f64, dy: f64 },
+    /// Click "left" (the default), "right", "middle", "back", "forward" or
+    /// "button<n>", `clicks` times in a row
+    Click {
+        #[serde(default)]
+        button: Option<String>,
+        #[serde(default)]
+        clicks: Option<u32>,
+    },
+    /// Scroll "up", "down", "left" or "right"
+    Scroll { direction: String },
+    /// Run any `:` command line, e.g. "mark a" or "set repeat_delay_ms=20"
+    Command { line: String },
+    /// Re-read the config file
+    Reload,
+    /// Stop the daemon
+    Quit,
+    /// Keep the connection open and get a status response every time the
+    /// mode, profile, selection or pending input changes
+    Watch,
+}
+
+/// The daemon's answer to a request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+impl Response {
+    pub fn message(message: String) -> Self {
+        Response {
+            ok: true,
+            message: Some(message).filter(|message| !message.is_empty()),
+            ..Default::default()
+        }
+    }
+
+    pub fn error(error: String) -> Self {
+        Response {
+            ok: false,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+/// Snapshot of the daemon state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// "navigation", "typing" or "command"
+    pub mode: String,
+    pub x: f64,
+    pub y: f64,
+    pub profile: Option<String>,
+    pub selection: bool,
+    /// Keys typed but not yet run, e.g. ":goto 10" while in the command line
+    #[serde(default)]
+    pub pending: String,
+    /// Register a macro is being recorded into
+    #[serde(default)]
+    pub recording: Option<String>,
+    /// Button held in drag mode, with its modifiers, e.g. "ctrl+left"
+    #[serde(default)]
+    pub drag: Option<String>,
+    /// Whether the autoclicker is running
+    #[serde(default)]
+    pub autoclick: bool,
+    /// WM_CLASS of the focused window, when known
+    pub window: Option<String>,
+}
+
+impl Status {
+    /// Whether anything a status bar shows differs; the cursor position is ignored
+    pub fn same_indicator(&self, other: &Status) -> bool {
+        self.mode == other.mode
+            && self.profile == other.profile
+            && self.selection == other.selection
+            && self.pending == other.pending
+            && self.recording == other.recording
+            && self.drag == other.drag
+            && self.autoclick == other.autoclick
+    }
+}
+
+/// Where the control socket lives: $XDG_RUNTIME_DIR/vinav.sock, or a
+/// per-user directory in /tmp when there is no runtime directory
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("vinav.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+            std::env::temp_dir().join(format!("vinav-{}", user)).join("vinav.sock")
+        }
+    }
+}
+
+/// Create the directory holding the socket, readable only by this user.
+///
+/// The socket is bound inside it, so nobody else can connect even before the
+/// socket's own permissions are set. A directory that already exists, like
+/// $XDG_RUNTIME_DIR, must be a real directory owned by this user and closed
+/// to everyone else, otherwise someone else could have made it first to
+/// listen in.
+#[cfg(unix)]
+pub fn create_socket_dir(socket: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    let Some(dir) = socket.parent() else {
+        return Ok(());
+    };
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            let metadata = std::fs::symlink_metadata(dir)?;
+            // SAFETY: getuid has no preconditions and cannot fail
+            let uid = unsafe { libc::getuid() };
+            if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("{} must be a directory owned by and only open to your user", dir.display()),
+                ));
+            }
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> Request {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn requests_read_from_json() {
+        assert_eq!(request(r#"{"cmd":"status"}"#), Request::Status);
+        assert_eq!(request(r#"{"cmd":"move_to","x":640,"y":400.5}"#), Request::MoveTo { x: 640.0, y: 400.5 });
+        assert_eq!(request(r#"{"cmd":"move_by","dx":-20,"dy":0}"#), Request::MoveBy { dx: -20.0, dy: 0.0 });
+        assert_eq!(
+            request(r#"{"cmd":"set_mode","mode":"typing"}"#),
+            Request::SetMode { mode: "typing".to_string() }
+        );
+        // Button and clicks may be left out
+        assert_eq!(request(r#"{"cmd":"click"}"#), Request::Click { button: None, clicks: None });
+        assert_eq!(
+            request(r#"{"cmd":"click","button":"right","clicks":2}"#),
+            Request::Click { button: Some("right".to_string()), clicks: Some(2) }
+        );
+        assert_eq!(
+            request(r#"{"cmd":"command","line":"mark a"}"#),
+            Request::Command { line: "mark a".to_string() }
+        );
+        assert_eq!(request(r#"{"cmd":"watch"}"#), Request::Watch);
+
+        for bad in [r#"{"cmd":"fly"}"#, r#"{"cmd":"move_to","x":1}"#, r#"{"x":1,"y":2}"#, "status"] {
+            assert!(serde_json::from_str::<Request>(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn requests_write_the_cmd_tag() {
+        let json = serde_json::to_string(&Request::Scroll { direction: "down".to_string() }).unwrap();
+        assert_eq!(json, r#"{"cmd":"scroll","direction":"down"}"#);
+        assert_eq!(serde_json::to_string(&Request::Reload).unwrap(), r#"{"cmd":"reload"}"#);
+    }
+
+    #[test]
+    fn responses_leave_out_empty_fields() {
+        let json = |response: &Response| serde_json::to_string(response).unwrap();
+        assert_eq!(
+            json(&Response::message("Moved to (640, 400)".to_string())),
+            r#"{"ok":true,"message":"Moved to (640, 400)"}"#
+        );
+        assert_eq!(json(&Response::message(String::new())), r#"{"ok":true}"#);
+        assert_eq!(
+            json(&Response::error("Unknown mode: fly".to_string())),
+            r#"{"ok":false,"error":"Unknown mode: fly"}"#
+        );
+    }
+
+    fn status() -> Status {
+        Status {
+            mode: "navigation".to_string(),
+            x: 640.0,
+            y: 400.0,
+            profile: Some("design".to_string()),
+            selection: false,
+            pending: String::new(),
+            recording: None,
+            drag: None,
+            autoclick: false,
+            window: None,
+        }
+    }
+
+    #[test]
+    fn status_responses_round_trip() {
+        let response = Response {
+            ok: true,
+            status: Some(status()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+
+        // Fields added later may be missing in answers from an older daemon
+        let old: Response = serde_json::from_str(
+            r#"{"ok":true,"status":{"mode":"typing","x":1,"y":2,"profile":null,"selection":true,"window":null}}"#,
+        )
+        .unwrap();
+        let old = old.status.unwrap();
+        assert_eq!((old.mode.as_str(), old.selection, old.pending.as_str()), ("typing", true, ""));
+    }
+
+    #[test]
+    fn same_indicator_ignores_the_position() {
+        let moved = Status { x: 10.0, y: 20.0, ..status() };
+        assert!(status().same_indicator(&moved));
+        let selecting = Status { selection: true, ..status() };
+        assert!(!status().same_indicator(&selecting));
+        let typing = Status { pending: "3".to_string(), ..status() };
+        assert!(!status().same_indicator(&typing));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_dir_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = std::env::temp_dir().join(format!("vinav-socket-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let socket = base.join("run").join("vinav.sock");
+        create_socket_dir(&socket).unwrap();
+        let mode = std::fs::metadata(base.join("run")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // An existing private directory is fine, one others can open is not
+        create_socket_dir(&socket).unwrap();
+        std::fs::set_permissions(base.join("run"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(create_socket_dir(&socket).is_err());
+
+        // Nor is a link to a private directory made by someone else
+        let link = base.join("link");
+        std::os::unix::fs::symlink(base.join("run"), &link).unwrap();
+        std::fs::set_permissions(base.join("run"), std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(create_socket_dir(&link.join("vinav.sock")).is_err());
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+//! Input journal: a line-based record of grabbed input, for bug reports and replays.
+//!
+//! `run --record <file>` writes a header and then one tab-separated line per event:
+//!
+//! ```text
+//! # vinav journal 1
+//! # screen 1920 1080
+//! 0      B  press          KeyH  h
+//! 31     B  release        KeyH
+//! 540    P  move           812   430
+//! 600    P  button_press   Left
+//! 700    P  wheel          0     -120
+//! 900    P  press          ?
+//! 1200   -  focus          Navigator  firefox
+//! ```
+//!
+//! Columns are milliseconds since recording started, whether the event was
+//! passed on (P) or blocked (B), the event and its arguments. Keys typed in
+//! typing mode are written as `?` unless recording with --record-typing.
+
+use rdev::{Button, Event, EventType, Key};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const HEADER: &str = "# vinav journal 1";
+
+/// One line of a journal
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Input {
+        at_ms: u64,
+        event_type: EventType,
+        name: Option<String>,
+        passed: bool,
+    },
+    /// A key typed in typing mode, left out of the journal
+    Redacted { at_ms: u64 },
+    /// The focused window changed, as (instance, class)
+    Focus {
+        at_ms: u64,
+        window: Option<(String, String)>,
+    },
+}
+
+impl Entry {
+    pub fn at_ms(&self) -> u64 {
+        match self {
+            Entry::Input { at_ms, .. } | Entry::Redacted { at_ms } | Entry::Focus { at_ms, .. } => {
+                *at_ms
+            }
+        }
+    }
+}
+
+/// A journal read back from disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal {
+    pub screen: Option<(u64, u64)>,
+    pub entries: Vec<Entry>,
+}
+
+/// Writes a journal while vim navigation runs
+pub struct Recorder {
+    file: Mutex<File>,
+    started: Instant,
+    include_typing: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, screen: (u64, u64), include_typing: bool) -> std::io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "# screen {} {}", screen.0, screen.1)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+            started: Instant::now(),
+            include_typing,
+        })
+    }
+
+    /// Record a grabbed event and whether it was passed on.
+    /// `typing` is whether typing mode was active when the event arrived.
+    pub fn record(&self, event: &Event, passed: bool, typing: bool) {
+        let is_key = matches!(event.event_type, EventType::KeyPress(_) | EventType::KeyRelease(_));
+        let mut line = format_event(&event.event_type);
+        if is_key && typing && passed && !self.include_typing && !is_modifier(&event.event_type) {
+            line = match event.event_type {
+                EventType::KeyPress(_) => "press\t?".to_string(),
+                _ => "release\t?".to_string(),
+            };
+        } else if let Some(name) = event.name.as_deref().filter(|name| is_plain(name)) {
+            if is_key {
+                line = format!("{}\t{}", line, name);
+            }
+        }
+        let decision = if passed { "P" } else { "B" };
+        self.write(&format!("{}\t{}", decision, line));
+    }
+
+    /// Record that the focused window changed
+    pub fn record_focus(&self, window: Option<(&str, &str)>) {
+        match window {
+            Some((instance, class)) if is_plain(instance) && is_plain(class) => {
+                self.write(&format!("-\tfocus\t{}\t{}", instance, class))
+            }
+            Some(_) => self.write("-\tfocus\t?\t?"),
+            None => self.write("-\tfocus"),
+        }
+    }
+
+    fn write(&self, line: &str) {
+        let at_ms = self.started.elapsed().as_millis();
+        if let Ok(mut file) = self.file.lock() {
+            // A journal is a debugging aid, losing a line must not stop input handling
+            let _ = writeln!(file, "{}\t{}", at_ms, line);
+        }
+    }
+}
+
+/// Text that fits in a single field
+fn is_plain(text: &str) -> bool {
+    !text.is_empty() && !text.contains(|c: char| c.is_control())
+}
+
+fn is_modifier(event_type: &EventType) -> bool {
+    matches!(
+        event_type,
+        EventType::KeyPress(key) | EventType::KeyRelease(key) if matches!(
+            key,
+            Key::ShiftLeft
+                | Key::ShiftRight
+                | Key::ControlLeft
+                | Key::ControlRight
+                | Key::Alt
+                | Key::AltGr
+                | Key::MetaLeft
+                | Key::MetaRight
+        )
+    )
+}
+
+/// Format an event as tab-separated fields, the same way journals and replays print them
+pub fn format_event(event_type: &EventType) -> String {
+    match event_type {
+        EventType::KeyPress(key) => format!("press\t{:?}", key),
+        EventType::KeyRelease(key) => format!("release\t{:?}", key),
+        EventType::ButtonPress(button) => format!("button_press\t{:?}", button),
+        EventType::ButtonRelease(button) => format!("button_release\t{:?}", button),
+        EventType::MouseMove { x, y } => format!("move\t{}\t{}", x, y),
+        EventType::Wheel { delta_x, delta_y } => format!("wheel\t{}\t{}", delta_x, delta_y),
+    }
+}
+
+/// Parse the fields written by `format_event`
+pub fn parse_event(fields: &[&str]) -> Result<EventType, String> {
+    let number = |value: &str| {
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("invalid number: {}", value))
+    };
+    let delta = |value: &str| {
+        value
+            .parse::<i64>()
+            .map_err(|_| format!("invalid wheel delta: {}", value))
+    };
+    match fields {
+        ["press", key, ..] => parse_key(key).map(EventType::KeyPress),
+        ["release", key, ..] => parse_key(key).map(EventType::KeyRelease),
+        ["button_press", button] => parse_button(button).map(EventType::ButtonPress),
+        ["button_release", button] => parse_button(button).map(EventType::ButtonRelease),
+        ["move", x, y] => Ok(EventType::MouseMove {
+            x: number(x)?,
+            y: number(y)?,
+        }),
+        ["wheel", delta_x, delta_y] => Ok(EventType::Wheel {
+            delta_x: delta(delta_x)?,
+            delta_y: delta(delta_y)?,
+        }),
+        _ => Err(format!("unknown event: {}", fields.join(" "))),
+    }
+}
+
+/// Read a journal written by `Recorder`
+pub fn read(path: &Path) -> Result<Journal, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line) != Some(HEADER) {
+        return Err(format!("{}: not a vinav journal", path.display()));
+    }
+
+    let mut journal = Journal {
+        screen: None,
+        entries: Vec::new(),
+    };
+    for (index, line) in lines {
+        let error = |message: String| format!("{}:{}: {}", path.display(), index + 1, message);
+        if let Some(comment) = line.strip_prefix('#') {
+            let fields: Vec<&str> = comment.split_whitespace().collect();
+            if let ["screen", width, height] = fields.as_slice() {
+                let width = width.parse().map_err(|_| error("invalid screen width".to_string()))?;
+                let height = height.parse().map_err(|_| error("invalid screen height".to_string()))?;
+                journal.screen = Some((width, height));
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [at_ms, decision, event @ ..] = fields.as_slice() else {
+            return Err(error("expected a time, a decision and an event".to_string()));
+        };
+        let at_ms = at_ms
+            .parse()
+            .map_err(|_| error(format!("invalid time: {}", at_ms)))?;
+        let entry = match (*decision, event) {
+            ("-", ["focus"]) => Entry::Focus { at_ms, window: None },
+            ("-", ["focus", instance, class]) => Entry::Focus {
+                at_ms,
+                window: Some((instance.to_string(), class.to_string())),
+            },
+            (_, ["press" | "release", "?"]) => Entry::Redacted { at_ms },
+            ("P" | "B", event) => {
+                let event_type = parse_event(event).map_err(error)?;
+                // Key presses may carry the typed text as a last field
+                let name = match (&event_type, event) {
+                    (EventType::KeyPress(_) | EventType::KeyRelease(_), [_, _, name]) => {
+                        Some(name.to_string())
+                    }
+                    _ => None,
+                };
+                Entry::Input {
+                    at_ms,
+                    event_type,
+                    name,
+                    passed: *decision == "P",
+                }
+            }
+            _ => return Err(error(format!("unknown decision: {}", decision))),
+        };
+        journal.entries.push(entry);
+    }
+    Ok(journal)
+}
+
+fn parse_button(name: &str) -> Result<Button, String> {
+    match name {
+        "Left" => Ok(Button::Left),
+        "Right" => Ok(Button::Right),
+        "Middle" => Ok(Button::Middle),
+        _ => name
+            .strip_prefix("Unknown(")
+            .and_then(|code| code.strip_suffix(')'))
+            .and_then(|code| code.parse().ok())
+            .map(Button::Unknown)
+            .ok_or_else(|| format!("unknown button: {}", name)),
+    }
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    if let Some(code) = name
+        .strip_prefix("Unknown(")
+        .and_then(|code| code.strip_suffix(')'))
+    {
+        return code
+            .parse()
+            .map(Key::Unknown)
+            .map_err(|_| format!("unknown key: {}", name));
+    }
+    ALL_KEYS
+        .iter()
+        .find(|key| format!("{:?}", key) == name)
+        .copied()
+        .ok_or_else(|| format!("unknown key: {}", name))
+}
+
+/// Every named rdev key, to turn journal key names back into keys
+const ALL_KEYS: &[Key] = &[
+    Key::Alt, Key::AltGr, Key::Backspace, Key::CapsLock, Key::ControlLeft, Key::ControlRight,
+    Key::Delete, Key::DownArrow, Key::End, Key::Escape, Key::F1, Key::F10, Key::F11, Key::F12,
+    Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::Home,
+    Key::LeftArrow, Key::MetaLeft, Key::MetaRight, Key::PageDown, Key::PageUp, Key::Return,
+    Key::RightArrow, Key::ShiftLeft, Key::ShiftRight, Key::Space, Key::Tab, Key::UpArrow,
+    Key::PrintScreen, Key::ScrollLock, Key::Pause, Key::NumLock, Key::BackQuote, Key::Num1,
+    Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Num0, Key::Minus, Key::Equal, Key::KeyQ, Key::KeyW, Key::KeyE, Key::KeyR, Key::KeyT,
+    Key::KeyY, Key::KeyU, Key::KeyI, Key::KeyO, Key::KeyP, Key::LeftBracket, Key::RightBracket,
+    Key::KeyA, Key::KeyS, Key::KeyD, Key::KeyF, Key::KeyG, Key::KeyH, Key::KeyJ, Key::KeyK,
+    Key::KeyL, Key::SemiColon, Key::Quote, Key::BackSlash, Key::IntlBackslash, Key::KeyZ,
+    Key::KeyX, Key::KeyC, Key::KeyV, Key::KeyB, Key::KeyN, Key::KeyM, Key::Comma, Key::Dot,
+    Key::Slash, Key::Insert, Key::KpReturn, Key::KpMinus, Key::KpPlus, Key::KpMultiply,
+    Key::KpDivide, Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4, Key::Kp5, Key::Kp6,
+    Key::Kp7, Key::Kp8, Key::Kp9, Key::KpDelete, Key::Function,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_text(name: &str, text: &str) -> Result<Journal, String> {
+        let path = std::env::temp_dir().join(format!("vinav-journal-{}-{}.tsv", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let journal = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        journal
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events = [
+            EventType::KeyPress(Key::KeyH),
+            EventType::KeyRelease(Key::SemiColon),
+            EventType::KeyPress(Key::Unknown(65027)),
+            EventType::ButtonPress(Button::Left),
+            EventType::ButtonRelease(Button::Unknown(8)),
+            EventType::MouseMove { x: 812.5, y: 430.0 },
+            EventType::Wheel { delta_x: 0, delta_y: -120 },
+        ];
+        for event in events {
+            let line = format_event(&event);
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(parse_event(&fields), Ok(event), "{}", line);
+        }
+    }
+
+    #[test]
+    fn reads_every_kind_of_line() {
+        let journal = read_text(
+            "kinds",
+            "# vinav journal 1\n\
+             # screen 2560 1440\n\
+             0\tB\tpress\tKeyH\th\n\
+             31\tB\trelease\tKeyH\n\
+             540\tP\tmove\t812\t430\n\
+             900\tP\tpress\t?\n\
+             \n\
+             1200\t-\tfocus\tNavigator\tfirefox\n\
+             1300\t-\tfocus\n",
+        )
+        .unwrap();
+        assert_eq!(journal.screen, Some((2560, 1440)));
+        assert_eq!(
+            journal.entries,
+            vec![
+                Entry::Input {
+                    at_ms: 0,
+                    event_type: EventType::KeyPress(Key::KeyH),
+                    name: Some("h".to_string()),
+                    passed: false,
+                },
+                Entry::Input {
+                    at_ms: 31,
+                    event_type: EventType::KeyRelease(Key::KeyH),
+                    name: None,
+                    passed: false,
+                },
+                Entry::Input {
+                    at_ms: 540,
+                    event_type: EventType::MouseMove { x: 812.0, y: 430.0 },
+                    name: None,
+                    passed: true,
+                },
+                Entry::Redacted { at_ms: 900 },
+                Entry::Focus {
+                    at_ms: 1200,
+                    window: Some(("Navigator".to_string(), "firefox".to_string())),
+                },
+                Entry::Focus { at_ms: 1300, window: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        assert!(read_text("header", "0\tB\tpress\tKeyH\n")
+            .unwrap_err()
+            .ends_with("not a vinav journal"));
+        let error = read_text("line", "# vinav journal 1\n0\tB\tpress\tKeyH\nsoon\tB\tpress\tKeyJ\n").unwrap_err();
+        assert!(error.ends_with(":3: invalid time: soon"), "{}", error);
+        let error = read_text("event", "# vinav journal 1\n0\tB\tjump\n").unwrap_err();
+        assert!(error.ends_with(":2: unknown event: jump"), "{}", error);
+        let error = read_text("decision", "# vinav journal 1\n0\tX\tpress\tKeyH\n").unwrap_err();
+        assert!(error.ends_with(":2: unknown decision: X"), "{}", error);
+    }
+}
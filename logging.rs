@@ -0,0 +1,271 @@
+//! Leveled logging for the daemon.
+//!
+//! Every message has a target naming the part of the program it comes from
+//! (config, mode, movement, mouse, clipboard, command, control, focus, hooks,
+//! macro, input, inject), so one
+//! part can be made chattier than the rest, e.g. `--log-level info,movement=trace`.
+//! Lines go to stderr or to a log file, as plain text or as JSON.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How and where to log, from the command line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogOptions {
+    /// -v raises the level by one step per flag, -q lowers it
+    pub verbosity: i8,
+    /// Filter like "debug" or "warn,movement=trace", overrides verbosity
+    pub filter: Option<String>,
+    /// Append to this file instead of writing to stderr
+    pub file: Option<PathBuf>,
+    /// One JSON object per line instead of plain text
+    pub json: bool,
+}
+
+impl LogOptions {
+    /// Command line flags that reproduce these options, for the background process
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let flag = if self.verbosity > 0 { "-v" } else { "-q" };
+        for _ in 0..self.verbosity.unsigned_abs() {
+            args.push(flag.to_string());
+        }
+        if let Some(filter) = &self.filter {
+            args.extend(["--log-level".to_string(), filter.clone()]);
+        }
+        if let Some(file) = &self.file {
+            args.extend(["--log-file".to_string(), file.display().to_string()]);
+        }
+        if self.json {
+            args.push("--log-json".to_string());
+        }
+        args
+    }
+}
+
+/// Levels from a filter like "info,movement=trace,control=off"
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Level for targets not listed, when given
+    pub default: Option<LevelFilter>,
+    pub targets: Vec<(String, LevelFilter)>,
+}
+
+pub fn parse_filter(spec: &str) -> Result<Filter, String> {
+    let mut default = None;
+    let mut targets = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let parse_level = |level: &str| {
+            level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("Unknown log level: {}", level))
+        };
+        match part.split_once('=') {
+            Some((target, level)) => targets.push((target.trim().to_string(), parse_level(level.trim())?)),
+            None => default = Some(parse_level(part)?),
+        }
+    }
+    Ok(Filter { default, targets })
+}
+
+fn level_for_verbosity(verbosity: i8) -> LevelFilter {
+    match verbosity {
+        i8::MIN..=-2 => LevelFilter::Error,
+        -1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+struct Logger {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+    json: bool,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(name, _)| name == target)
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn format(&self, record: &Record) -> String {
+        if self.json {
+            serde_json::json!({
+                "time": timestamp(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            })
+            .to_string()
+        } else {
+            format!(
+                "{} {:<5} {}: {}",
+                timestamp(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        if let Ok(mut output) = self.output.lock() {
+            // There is nowhere left to report a failed log write
+            let _ = writeln!(output, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut output) = self.output.lock() {
+            let _ = output.flush();
+        }
+    }
+}
+
+/// Install the logger. Called once, before the daemon starts.
+pub fn init(options: &LogOptions) -> Result<(), String> {
+    let filter = options
+        .filter
+        .clone()
+        .or_else(|| std::env::var("VINAV_LOG").ok());
+    let Filter { default, targets } = match filter {
+        Some(spec) => parse_filter(&spec)?,
+        None => Filter::default(),
+    };
+    let default = default.unwrap_or_else(|| level_for_verbosity(options.verbosity));
+
+    let output: Box<dyn Write + Send> = match &options.file {
+        Some(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Could not open log file {}: {}", path.display(), e))?,
+        ),
+        None => Box::new(std::io::stderr()),
+    };
+
+    let max_level = targets
+        .iter()
+        .map(|(_, level)| *level)
+        .chain([default])
+        .max()
+        .unwrap_or(LevelFilter::Info);
+    let logger = Logger {
+        default,
+        targets,
+        json: options.json,
+        output: Mutex::new(output),
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| e.to_string())?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Current time as an RFC 3339 UTC timestamp with milliseconds
+fn timestamp() -> String {
+    format_time(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+    )
+}
+
+/// Time since the epoch as an RFC 3339 UTC timestamp with milliseconds
+fn format_time(now: Duration) -> String {
+    let seconds = now.as_secs();
+    let (hour, minute, second) = ((seconds / 3600) % 24, (seconds / 60) % 60, seconds % 60);
+
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        now.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_filter_reads_default_and_target_levels() {
+        let filter = parse_filter("info, movement=trace,control = off").unwrap();
+        assert_eq!(filter.default, Some(LevelFilter::Info));
+        assert_eq!(
+            filter.targets,
+            [
+                ("movement".to_string(), LevelFilter::Trace),
+                ("control".to_string(), LevelFilter::Off)
+            ]
+        );
+
+        let filter = parse_filter("macro=DEBUG,").unwrap();
+        assert_eq!(filter.default, None);
+        assert_eq!(filter.targets, [("macro".to_string(), LevelFilter::Debug)]);
+        assert_eq!(parse_filter("").unwrap().default, None);
+    }
+
+    #[test]
+    fn parse_filter_rejects_unknown_levels() {
+        assert_eq!(parse_filter("loud").unwrap_err(), "Unknown log level: loud");
+        assert_eq!(parse_filter("info,mouse=chatty").unwrap_err(), "Unknown log level: chatty");
+        assert_eq!(parse_filter("mouse=").unwrap_err(), "Unknown log level: ");
+    }
+
+    #[test]
+    fn verbosity_steps_through_the_levels() {
+        assert_eq!(level_for_verbosity(-3), LevelFilter::Error);
+        assert_eq!(level_for_verbosity(-1), LevelFilter::Warn);
+        assert_eq!(level_for_verbosity(0), LevelFilter::Info);
+        assert_eq!(level_for_verbosity(2), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn format_time_writes_rfc_3339() {
+        let at = |seconds: u64, millis: u64| format_time(Duration::from_millis(seconds * 1000 + millis));
+        assert_eq!(at(0, 0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(at(1_704_067_199, 999), "2023-12-31T23:59:59.999Z");
+        // Leap days, including the 400-year rule and a century that is not a leap year
+        assert_eq!(at(1_709_164_800, 0), "2024-02-29T00:00:00.000Z");
+        assert_eq!(at(1_735_603_200, 0), "2024-12-31T00:00:00.000Z");
+        assert_eq!(at(951_827_696, 7), "2000-02-29T12:34:56.007Z");
+        assert_eq!(at(4_107_542_400 - 86_400, 0), "2100-02-28T00:00:00.000Z");
+        assert_eq!(at(4_107_542_400, 0), "2100-03-01T00:00:00.000Z");
+    }
+}
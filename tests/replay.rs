@@ -0,0 +1,123 @@
+//! Replays recorded journals and compares what would be sent with the expected output.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+/// An empty directory for the replay's runtime and config files
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vinav-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn replay(journal: &str, config: &Path, scratch: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vim_navigation"))
+        .arg("--config")
+        .arg(config)
+        .arg("replay")
+        .arg(fixture(&format!("{}.tsv", journal)))
+        .arg("--expect")
+        .arg(fixture(&format!("{}.expected", journal)))
+        .env("XDG_RUNTIME_DIR", scratch.join("runtime"))
+        .env("XDG_CONFIG_HOME", scratch.join("config"))
+        .env("XDG_STATE_HOME", scratch.join("state"))
+        .output()
+        .unwrap()
+}
+
+/// Replay a fixture with the default config and check it sends what it did when recorded
+fn assert_replay_matches(journal: &str) {
+    assert_replay_matches_with(journal, "replay.toml");
+}
+
+/// Like assert_replay_matches, with a config from the fixtures
+fn assert_replay_matches_with(journal: &str, config: &str) {
+    let scratch = scratch_dir(journal);
+    let output = replay(journal, &fixture(config), &scratch);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn quit_ends_the_replay_and_releases_the_selection() {
+    let scratch = scratch_dir("quit");
+    // Stands in for the socket of a daemon running next to the replay
+    let socket = scratch.join("runtime").join("vinav.sock");
+    std::fs::create_dir_all(socket.parent().unwrap()).unwrap();
+    std::fs::write(&socket, "").unwrap();
+
+    let output = replay("quit_during_selection", &fixture("replay.toml"), &scratch);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(socket.exists(), "the replay removed the daemon's socket");
+    std::fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn set_with_save_applies_without_saving() {
+    let scratch = scratch_dir("save");
+    let config = scratch.join("replay.toml");
+    std::fs::copy(fixture("replay.toml"), &config).unwrap();
+
+    let output = replay("save_setting", &config, &scratch);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(
+        std::fs::read_to_string(&config).unwrap(),
+        std::fs::read_to_string(fixture("replay.toml")).unwrap()
+    );
+    assert!(!scratch.join("config").exists());
+    std::fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn linewise_selection_extends_to_line_edges() {
+    assert_replay_matches("linewise_selection");
+}
+
+#[test]
+fn repeat_keeps_its_count_until_a_new_one_is_typed() {
+    assert_replay_matches("repeat_with_count");
+}
+
+#[test]
+fn text_objects_click_once_per_word_line_or_paragraph() {
+    assert_replay_matches("text_objects");
+}
+
+#[test]
+fn v_waits_for_a_motion_and_esc_or_v_cancel_it() {
+    assert_replay_matches("visual_start");
+}
+
+#[test]
+fn visual_operators_release_the_button_and_leave_visual_mode() {
+    assert_replay_matches("visual_operators");
+    // y, d, p and Esc each let go of the selection's button exactly once
+    let expected = std::fs::read_to_string(fixture("visual_operators.expected")).unwrap();
+    let count = |kind: &str| expected.lines().filter(|line| line.split('\t').nth(1) == Some(kind)).count();
+    assert_eq!(count("button_release"), count("button_press"));
+}
+
+#[test]
+fn drags_hold_their_modifiers_and_cancel_with_escape_first() {
+    assert_replay_matches_with("drag", "drag.toml");
+}
+
+#[test]
+fn counts_before_a_click_are_capped_at_a_triple_click() {
+    assert_replay_matches("multi_click");
+}
+
+#[test]
+fn esc_and_typing_mode_release_held_buttons() {
+    assert_replay_matches("held_release");
+}
@@ -1,11 +1,18 @@
+mod control;
+mod journal;
+mod logging;
+
 use config::{Config, ConfigError, File};
+use log::{debug, error, info, trace, warn};
 use rdev::{
     display_size, grab, simulate, Button, DisplayError, Event, EventType, GrabError, Key,
     SimulateError,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError, TryLockError};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -19,8 +26,149 @@ struct KeyCombination {
     cmd: bool, // Meta/Command key on macOS
 }
 
-/// Configuration structure for vim navigation
+/// Key bindings resolved from the config, rebuilt whenever the config changes
+#[derive(Debug, Clone)]
+struct Keymap {
+    left: Key,
+    down: Key,
+    up: Key,
+    right: Key,
+    click: Key,
+    right_click: Key,
+    select_toggle: Key,
+    goto_top: Key,
+    goto_bottom: Key,
+    yank: Key,
+    paste: Key,
+    macro_record: Key,
+    middle_click: Key,
+    back: Key,
+    forward: Key,
+    repeat: Key,
+    drag: Key,
+    drag_cancel: Key,
+    drag_button: Button,
+    drag_modifiers: Vec<Key>,
+    autoclick: Key,
+    autoclick_stop: Key,
+    text_object: Key,
+    object_word: Key,
+    object_line: Key,
+    object_paragraph: Key,
+    cut: Key,
+    select_cancel: Key,
+    select_line: Option<KeyCombination>,
+    double_click: Option<KeyCombination>,
+    triple_click: Option<KeyCombination>,
+    toggle_mode: Option<KeyCombination>,
+    cycle_profile: Option<KeyCombination>,
+    /// [bindings] entries, checked before the built-in keys
+    commands: Vec<(KeyCombination, Action)>,
+}
+
+impl Keymap {
+    fn from_config(config: &VimNavConfig) -> Self {
+        let key = |name: &str, fallback: Key| config.string_to_key(name).unwrap_or(fallback);
+        Keymap {
+            left: key(&config.key_left, Key::KeyH),
+            down: key(&config.key_down, Key::KeyJ),
+            up: key(&config.key_up, Key::KeyK),
+            right: key(&config.key_right, Key::KeyL),
+            click: key(&config.key_click, Key::Return),
+            right_click: key(&config.key_right_click, Key::KeyI),
+            select_toggle: key(&config.key_select_toggle, Key::KeyV),
+            goto_top: key(&config.key_goto_top, Key::KeyG),
+            goto_bottom: key(&config.key_goto_bottom, Key::KeyG),
+            yank: key(&config.key_yank, Key::KeyY),
+            paste: key(&config.key_paste, Key::KeyP),
+            macro_record: key(&config.key_macro_record, Key::KeyQ),
+            middle_click: key(&config.key_middle_click, Key::KeyM),
+            back: key(&config.key_back, Key::LeftBracket),
+            forward: key(&config.key_forward, Key::RightBracket),
+            repeat: key(&config.key_repeat, Key::Dot),
+            drag: key(&config.key_drag, Key::KeyD),
+            drag_cancel: key(&config.key_drag_cancel, Key::Escape),
+            drag_button: parse_button(&config.drag_button).unwrap_or(Button::Left),
+            drag_modifiers: config
+                .drag_modifiers
+                .iter()
+                .filter_map(|name| parse_modifier(name))
+                .collect(),
+            autoclick: key(&config.key_autoclick, Key::KeyA),
+            autoclick_stop: key(&config.key_autoclick_stop, Key::Escape),
+            text_object: key(&config.key_text_object, Key::KeyI),
+            object_word: key(&config.key_object_word, Key::KeyW),
+            object_line: key(&config.key_object_line, Key::KeyL),
+            object_paragraph: key(&config.key_object_paragraph, Key::KeyP),
+            cut: key(&config.key_cut, Key::KeyD),
+            select_cancel: key(&config.key_select_cancel, Key::Escape),
+            select_line: config.parse_key_combination(&config.key_select_line),
+            double_click: config.parse_key_combination(&config.key_double_click),
+            triple_click: config.parse_key_combination(&config.key_triple_click),
+            toggle_mode: config.parse_key_combination(&config.key_toggle_mode),
+            cycle_profile: config.parse_key_combination(&config.key_cycle_profile),
+            commands: config
+                .bindings
+                .iter()
+                .filter_map(|(keys, line)| {
+                    Some((config.parse_key_combination(keys)?, parse_command(line).ok()?))
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether a single-key binding uses this key
+    fn is_bound(&self, key: Key) -> bool {
+        self.is_movement_key(key)
+            || [
+                self.click,
+                self.right_click,
+                self.select_toggle,
+                self.goto_top,
+                self.goto_bottom,
+                self.yank,
+                self.paste,
+                self.macro_record,
+                self.middle_click,
+                self.back,
+                self.forward,
+                self.repeat,
+                self.drag,
+                self.autoclick,
+            ]
+            .contains(&key)
+    }
+
+    /// Text object typed after v and key_text_object, like the w in viw
+    fn text_object(&self, key: Key) -> Option<TextObject> {
+        match key {
+            k if k == self.object_word => Some(TextObject::Word),
+            k if k == self.object_line => Some(TextObject::Line),
+            k if k == self.object_paragraph => Some(TextObject::Paragraph),
+            _ => None,
+        }
+    }
+
+    fn is_movement_key(&self, key: Key) -> bool {
+        key == self.left || key == self.down || key == self.up || key == self.right
+    }
+
+    /// Scroll direction for Shift+movement key
+    fn scroll_direction(&self, key: Key) -> &'static str {
+        match key {
+            k if k == self.left => "left",
+            k if k == self.down => "down",
+            k if k == self.up => "up",
+            k if k == self.right => "right",
+            _ => "",
+        }
+    }
+}
+
+/// Configuration structure for vim navigation.
+/// Missing fields fall back to their default, so partial config files work.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 struct VimNavConfig {
     /// Initial movement speed in pixels
     pub initial_move_step: f64,
@@ -34,8 +182,13 @@ struct VimNavConfig {
     pub repeat_delay_ms: u64,
     /// Delay between OS events in milliseconds
     pub move_delay_ms: u64,
+    /// Gap between the presses and releases of a double or triple click, in
+    /// milliseconds. Must stay well below the OS double-click interval.
+    pub click_gap_ms: u64,
     /// Precision mode divisor (how much slower when space is held)
     pub precision_divisor: f64,
+    /// Pixels moved per count by a movement key typed after a count, like 5l
+    pub hop_step: f64,
     /// Navigation keys
     pub key_left: String,
     pub key_down: String,
@@ -44,11 +197,150 @@ struct VimNavConfig {
     pub key_click: String,
     pub key_toggle_mode: String, // Single key to toggle between nav/typing modes
     pub key_right_click: String,
+    pub key_double_click: String, // Double click, e.g. to select a word or open a file
+    pub key_triple_click: String, // Triple click, e.g. to select a line
+    pub key_middle_click: String, // Middle click, pastes the primary selection on X11
+    pub key_back: String,         // Back (X1) mouse button
+    pub key_forward: String,      // Forward (X2) mouse button
     pub key_select_toggle: String, // Toggle text selection mode
     pub key_goto_top: String,      // Go to top of screen (gg equivalent)
     pub key_goto_bottom: String,   // Go to bottom of screen (G equivalent)
     pub key_yank: String,          // Copy/yank (y key)
     pub key_paste: String,         // Paste (p key)
+    pub key_cycle_profile: String, // Switch to the next profile
+    pub key_macro_record: String,  // Start/stop recording a macro (q{register})
+    pub key_repeat: String,        // Repeat the last action (. in vim)
+    pub key_drag: String,          // Start or end a drag
+    pub key_drag_cancel: String,   // Press Escape, then end the drag
+    /// Button held by a drag started with key_drag, e.g. left, right or middle
+    pub drag_button: String,
+    /// Modifiers held for the whole drag, e.g. ["ctrl"] to copy files
+    pub drag_modifiers: Vec<String>,
+    /// Profile used while dragging, for slower and more precise movement
+    pub drag_profile: Option<String>,
+    pub key_autoclick: String,      // Start or stop clicking repeatedly, 50a clicks 50 times
+    pub key_autoclick_stop: String, // Stop the autoclicker
+    /// Button clicked by the autoclicker
+    pub autoclick_button: String,
+    /// Time between autoclicker clicks in milliseconds
+    pub autoclick_interval_ms: u64,
+    /// Most clicks one autoclicker run may make, even without a count
+    pub autoclick_max_clicks: u32,
+    pub key_text_object: String,      // After v: iw selects a word, il a line, ip a paragraph
+    pub key_object_word: String,      // viw: double click the word under the cursor
+    pub key_object_line: String,      // vil: triple click the line under the cursor
+    pub key_object_paragraph: String, // vip: triple click and drag down
+    /// How far vip drags down from the line under the cursor, in pixels
+    pub paragraph_drag: f64,
+    pub key_cut: String,           // Cut the selection (visual mode only)
+    pub key_select_cancel: String, // End the selection without keeping it (visual mode only)
+    pub key_select_line: String,   // Start or end a line-wise selection, like V in vim
+    /// How v selects: "drag" holds the left button while moving, "anchor"
+    /// clicks where it starts and Shift+clicks where it ends
+    pub selection_style: String,
+    /// Profile applied on startup (defaults to "default" when that profile exists)
+    pub profile: Option<String>,
+    /// Named sets of overrides for any of the fields above, e.g. [profiles.design]
+    pub profiles: BTreeMap<String, toml::Table>,
+    /// Per-application behavior, the first rule matching the focused window wins
+    pub app_rules: Vec<AppRule>,
+    /// Shell commands to run when the mode, selection, profile or config changes
+    pub hooks: Hooks,
+    /// Extra key bindings that run a `:` command line, e.g. "shift+m" = "press middle"
+    pub bindings: BTreeMap<String, String>,
+}
+
+/// Changes applied while a matching window has focus, from an [[app_rules]] entry
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+struct AppRule {
+    /// Regex matched against the window's WM_CLASS (class or instance name)
+    pub class: Option<String>,
+    /// Regex matched against the window title
+    pub title: Option<String>,
+    /// Profile to switch to while the window is focused
+    pub profile: Option<String>,
+    /// Key binding overrides (key_* options) while the window is focused
+    pub keymap: toml::Table,
+    /// Switch to typing mode so every key passes through, e.g. for nvim in a terminal
+    pub typing: bool,
+    /// class and title compiled, see patterns()
+    #[serde(skip)]
+    compiled: OnceLock<Option<RulePatterns>>,
+}
+
+/// An app rule's class and title regexes, None where the rule has no pattern
+type RulePatterns = (Option<regex::Regex>, Option<regex::Regex>);
+
+impl AppRule {
+    /// The compiled class and title regexes, or None if either is invalid.
+    /// The loader compiles them, so focus polls don't have to.
+    fn patterns(&self) -> Option<&RulePatterns> {
+        self.compiled
+            .get_or_init(|| {
+                let compile = |pattern: &Option<String>| pattern.as_deref().map(regex::Regex::new).transpose();
+                Some((compile(&self.class).ok()?, compile(&self.title).ok()?))
+            })
+            .as_ref()
+    }
+
+    /// Whether this rule applies to a window. Invalid regexes never match.
+    fn matches(&self, window: &FocusedWindow) -> bool {
+        let Some((class, title)) = self.patterns() else {
+            return false;
+        };
+        let is_match = |pattern: &Option<regex::Regex>, values: &[&str]| {
+            pattern
+                .as_ref()
+                .is_none_or(|re| values.iter().any(|value| re.is_match(value)))
+        };
+        is_match(class, &[&window.class, &window.instance]) && is_match(title, &[&window.title])
+    }
+
+    /// Describe what the rule does, for log messages
+    fn describe(&self) -> String {
+        let mut effects = Vec::new();
+        if self.typing {
+            effects.push("typing mode".to_string());
+        }
+        if let Some(profile) = &self.profile {
+            effects.push(format!("profile {}", profile));
+        }
+        if !self.keymap.is_empty() {
+            effects.push(format!("{} key override(s)", self.keymap.len()));
+        }
+        effects.join(", ")
+    }
+}
+
+/// Shell commands from the [hooks] section. Each runs in the background with
+/// the event details in VINAV_* environment variables.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct Hooks {
+    pub mode_enter: Option<String>,
+    pub mode_exit: Option<String>,
+    pub selection_start: Option<String>,
+    pub selection_end: Option<String>,
+    pub profile_switch: Option<String>,
+    pub config_reload: Option<String>,
+    pub exit: Option<String>,
+}
+
+impl Hooks {
+    fn command(&self, event: &str) -> Option<&str> {
+        let command = match event {
+            "mode_enter" => &self.mode_enter,
+            "mode_exit" => &self.mode_exit,
+            "selection_start" => &self.selection_start,
+            "selection_end" => &self.selection_end,
+            "profile_switch" => &self.profile_switch,
+            "config_reload" => &self.config_reload,
+            "exit" => &self.exit,
+            _ => &None,
+        };
+        command.as_deref().filter(|command| !command.trim().is_empty())
+    }
 }
 
 impl Default for VimNavConfig {
@@ -60,7 +352,9 @@ impl Default for VimNavConfig {
             acceleration_multiplier: 50.0, // Double the multiplier for faster acceleration
             repeat_delay_ms: 30,
             move_delay_ms: 15,
+            click_gap_ms: 20,
             precision_divisor: 50.0, // 100x slower by default
+            hop_step: 20.0,
             key_left: "h".to_string(),
             key_down: "j".to_string(),
             key_up: "k".to_string(),
@@ -68,42 +362,291 @@ impl Default for VimNavConfig {
             key_click: "return".to_string(),
             key_toggle_mode: "command+shift+s".to_string(),
             key_right_click: "i".to_string(),
+            key_double_click: "o".to_string(),
+            key_triple_click: "shift+o".to_string(),
+            key_middle_click: "m".to_string(),
+            key_back: "[".to_string(),
+            key_forward: "]".to_string(),
             key_select_toggle: "v".to_string(),
             key_goto_top: "g".to_string(),
             key_goto_bottom: "shift_g".to_string(),
             key_yank: "y".to_string(),
             key_paste: "p".to_string(),
+            key_cycle_profile: "shift+tab".to_string(),
+            key_macro_record: "q".to_string(),
+            key_repeat: ".".to_string(),
+            key_drag: "d".to_string(),
+            key_drag_cancel: "escape".to_string(),
+            drag_button: "left".to_string(),
+            drag_modifiers: Vec::new(),
+            drag_profile: None,
+            key_autoclick: "a".to_string(),
+            key_autoclick_stop: "escape".to_string(),
+            autoclick_button: "left".to_string(),
+            autoclick_interval_ms: 100,
+            autoclick_max_clicks: 1000,
+            key_text_object: "i".to_string(),
+            key_object_word: "w".to_string(),
+            key_object_line: "l".to_string(),
+            key_object_paragraph: "p".to_string(),
+            paragraph_drag: 100.0,
+            key_cut: "d".to_string(),
+            key_select_cancel: "escape".to_string(),
+            key_select_line: "shift+v".to_string(),
+            selection_style: "drag".to_string(),
+            profile: None,
+            profiles: BTreeMap::new(),
+            app_rules: Vec::new(),
+            hooks: Hooks::default(),
+            bindings: BTreeMap::new(),
         }
     }
 }
 
 impl VimNavConfig {
-    fn load() -> Result<Self, ConfigError> {
-        let settings = Config::builder()
-            .add_source(config::Environment::with_prefix("VIMNAV"))
-            .add_source(File::with_name("vim_navigation_config").required(false));
-
-        // Try to load from config file, fall back to defaults
-        match settings.build() {
-            Ok(config) => match config.try_deserialize() {
-                Ok(loaded_config) => {
-                    println!("Loaded configuration from vim_navigation_config.toml");
-                    Ok(loaded_config)
+    fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        match Self::load_checked(path) {
+            Ok((loaded_config, warnings)) => {
+                for warning in &warnings {
+                    warn!(target: "config", "{}", warning);
+                }
+                match path {
+                    Some(path) => info!(target: "config", "Loaded configuration from {}", path.display()),
+                    None => info!(target: "config", "No config file found, using default configuration"),
+                }
+                Ok(loaded_config)
+            }
+            Err(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    error!(target: "config", "{}", diagnostic);
+                }
+                Err(ConfigError::Message(
+                    "Config has errors, fix them or run with --check-config for details"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Load the config for inspection, printing only problems
+    fn load_quiet(path: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::load_checked(path).map(|(config, _)| config).map_err(|diagnostics| {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            ConfigError::Message("Config has errors".to_string())
+        })
+    }
+
+    /// Load the config without falling back to defaults, so reloads can keep the old config
+    fn try_load(path: Option<&Path>) -> Result<Self, String> {
+        match Self::load_checked(path) {
+            Ok((config, warnings)) => {
+                for warning in &warnings {
+                    warn!(target: "config", "{}", warning);
                 }
+                Ok(config)
+            }
+            Err(diagnostics) => Err(diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
+        }
+    }
+
+    /// Load and validate the config file and VIMNAV_* environment overrides.
+    /// Returns the config with any warnings, or every problem found if there are errors.
+    fn load_checked(
+        path: Option<&Path>,
+    ) -> Result<(Self, Vec<ConfigDiagnostic>), Vec<ConfigDiagnostic>> {
+        let mut diagnostics = Vec::new();
+        let file_name = |mut diagnostics: Vec<ConfigDiagnostic>| {
+            for diagnostic in &mut diagnostics {
+                diagnostic.file = path.map(|path| path.display().to_string());
+            }
+            diagnostics
+        };
+
+        // Check the file on its own first, so problems can point at a line
+        let text = match path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => Some(text),
                 Err(e) => {
-                    println!("Failed to parse config file: {}", e);
-                    println!("Using default configuration");
-                    Ok(Self::default())
+                    diagnostics.push(ConfigDiagnostic::error(None, e.to_string()));
+                    return Err(file_name(diagnostics));
                 }
             },
+            None => None,
+        };
+        if let Some(text) = &text {
+            Self::check_file(text, &mut diagnostics);
+            if diagnostics.iter().any(ConfigDiagnostic::is_error) {
+                return Err(file_name(diagnostics));
+            }
+        }
+
+        let mut settings =
+            Config::builder().add_source(config::Environment::with_prefix("VIMNAV"));
+        if let Some(path) = path {
+            settings = settings.add_source(File::from(path).format(config::FileFormat::Toml));
+        }
+        let loaded: Result<Self, ConfigError> =
+            settings.build().and_then(|config| config.try_deserialize());
+        let mut config = match loaded {
+            Ok(config) => config,
+            Err(e) => {
+                diagnostics.push(ConfigDiagnostic::error(None, e.to_string()));
+                return Err(file_name(diagnostics));
+            }
+        };
+
+        // The config crate lowercases keys, so take profile names straight from the file
+        if let Some(profiles) = text
+            .as_deref()
+            .and_then(|text| toml::from_str::<Self>(text).ok())
+            .map(|file_config| file_config.profiles)
+        {
+            config.profiles = profiles;
+        }
+
+        for mut diagnostic in config.validate() {
+            if let (Some(text), Some(field)) = (&text, &diagnostic.field) {
+                diagnostic.line = find_field_line(text, field);
+            }
+            diagnostics.push(diagnostic);
+        }
+
+        // Every profile must produce a usable config on its own
+        for name in config.profiles.keys() {
+            if let Err(e) = config.with_profile(name) {
+                let mut diagnostic = ConfigDiagnostic::error(None, e);
+                diagnostic.line = text
+                    .as_ref()
+                    .and_then(|text| find_section_line(text, &format!("profiles.{}", name)));
+                diagnostics.push(diagnostic);
+            }
+        }
+        for (index, rule) in config.app_rules.iter().enumerate() {
+            let line = text
+                .as_ref()
+                .and_then(|text| find_nth_line(text, "[[app_rules]]", index));
+            let mut error = |field: &str, message: String| {
+                let mut diagnostic =
+                    ConfigDiagnostic::error(Some(&format!("app_rules[{}].{}", index, field)), message);
+                diagnostic.line = line;
+                diagnostics.push(diagnostic);
+            };
+            for (field, pattern) in [("class", &rule.class), ("title", &rule.title)] {
+                if let Some(Err(e)) = pattern.as_ref().map(|pattern| regex::Regex::new(pattern)) {
+                    error(field, format!("invalid regex: {}", e));
+                }
+            }
+            if let Some(profile) = &rule.profile {
+                if !config.profiles.contains_key(profile) {
+                    error("profile", format!("no [profiles.{}] section", profile));
+                }
+            }
+            for key in rule.keymap.keys() {
+                if !key.starts_with("key_") || !CONFIG_KEYS.contains(&key.as_str()) {
+                    error("keymap", format!("{} is not a key binding option", key));
+                }
+            }
+            if let Err(e) = config.with_overrides(&rule.keymap, &format!("app_rules[{}].keymap", index)) {
+                error("keymap", e);
+            }
+        }
+
+        if let Some(profile) = &config.profile {
+            if !config.profiles.contains_key(profile) {
+                let mut diagnostic = ConfigDiagnostic::error(
+                    Some("profile"),
+                    format!("no [profiles.{}] section", profile),
+                );
+                diagnostic.line = text.as_ref().and_then(|text| find_field_line(text, "profile"));
+                diagnostics.push(diagnostic);
+            }
+        }
+
+        if let Some(profile) = &config.drag_profile {
+            if !config.profiles.contains_key(profile) {
+                let mut diagnostic = ConfigDiagnostic::error(
+                    Some("drag_profile"),
+                    format!("no [profiles.{}] section", profile),
+                );
+                diagnostic.line = text.as_ref().and_then(|text| find_field_line(text, "drag_profile"));
+                diagnostics.push(diagnostic);
+            }
+        }
+
+        if diagnostics.iter().any(ConfigDiagnostic::is_error) {
+            Err(file_name(diagnostics))
+        } else {
+            for rule in &config.app_rules {
+                rule.patterns();
+            }
+            Ok((config, file_name(diagnostics)))
+        }
+    }
+
+    /// Syntax, type and unknown-key checks against the raw file contents
+    fn check_file(text: &str, diagnostics: &mut Vec<ConfigDiagnostic>) {
+        let table = match toml::from_str::<toml::Table>(text) {
+            Ok(table) => table,
             Err(e) => {
-                println!("Failed to build config: {}", e);
-                println!("Using default configuration");
-                Ok(Self::default())
+                let mut diagnostic = ConfigDiagnostic::error(None, e.message().to_string());
+                diagnostic.line = e.span().map(|span| line_of_offset(text, span.start));
+                diagnostics.push(diagnostic);
+                return;
+            }
+        };
+
+        // Options inside profiles are checked the same way as top-level ones
+        let profile_options = table
+            .get("profiles")
+            .and_then(toml::Value::as_table)
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, profile)| profile.as_table())
+            .flat_map(|profile| profile.keys());
+        for key in table.keys().chain(profile_options) {
+            if CONFIG_KEYS.contains(&key.as_str()) || SECTION_KEYS.contains(&key.as_str()) {
+                continue;
             }
+            let message = match closest_config_key(key) {
+                Some(suggestion) => format!("unknown option (did you mean {}?)", suggestion),
+                None => "unknown option, it will be ignored".to_string(),
+            };
+            let mut diagnostic = ConfigDiagnostic::warning(Some(key), message);
+            diagnostic.line = find_field_line(text, key);
+            diagnostics.push(diagnostic);
+        }
+
+        if let Err(e) = toml::from_str::<Self>(text) {
+            let line = e.span().map(|span| line_of_offset(text, span.start));
+            // The error span points at the value, so recover the field from that line
+            let field = line
+                .and_then(|line| text.lines().nth(line - 1))
+                .and_then(|line| line.split_once('='))
+                .map(|(name, _)| name.trim().to_string());
+            let mut diagnostic = ConfigDiagnostic::error(field.as_deref(), e.message().to_string());
+            diagnostic.line = line;
+            diagnostics.push(diagnostic);
         }
     }
 
+    /// Describe every field that differs between two configs, one line per field
+    fn diff(&self, other: &Self) -> Vec<String> {
+        CONFIG_KEYS
+            .iter()
+            .filter_map(|key| {
+                let old = self.get_value(key)?;
+                let new = other.get_value(key)?;
+                (old != new).then(|| format!("  {}: {} -> {}", key, old, new))
+            })
+            .collect()
+    }
+
     /// Parse a key combination string like "command+shift+s" into a KeyCombination
     fn parse_key_combination(&self, key_str: &str) -> Option<KeyCombination> {
         let parts: Vec<&str> = key_str.split('+').collect();
@@ -141,17 +684,13 @@ impl VimNavConfig {
             })
         } else {
             // If no main key found, try parsing as a single key
-            if let Some(key) = self.string_to_key(key_str) {
-                Some(KeyCombination {
-                    key,
-                    ctrl: false,
-                    alt: false,
-                    shift: false,
-                    cmd: false,
-                })
-            } else {
-                None
-            }
+            self.string_to_key(key_str).map(|key| KeyCombination {
+                key,
+                ctrl: false,
+                alt: false,
+                shift: false,
+                cmd: false,
+            })
         }
     }
 
@@ -235,8 +774,12 @@ impl VimNavConfig {
             "shift_g" => Some(Key::KeyG), // We'll handle shift detection separately
             "space" => Some(Key::Space),
             "tab" => Some(Key::Tab),
+            "semicolon" => Some(Key::SemiColon),
+            "[" | "leftbracket" => Some(Key::LeftBracket),
+            "]" | "rightbracket" => Some(Key::RightBracket),
             "backspace" => Some(Key::Backspace),
             "delete" => Some(Key::Delete),
+            "." | "dot" => Some(Key::Dot),
             _ => None,
         }
     }
@@ -251,6 +794,545 @@ impl VimNavConfig {
             && key_combo.alt == alt_pressed
             && key_combo.cmd == cmd_pressed
     }
+
+    /// Check that every value is usable, returning one diagnostic per problem
+    fn validate(&self) -> Vec<ConfigDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut error = |field: &str, message: &str| {
+            diagnostics.push(ConfigDiagnostic::error(Some(field), message.to_string()))
+        };
+
+        for key in CONFIG_KEYS.iter().filter(|key| key.starts_with("key_")) {
+            let binding = self.get_value(key).unwrap_or_default();
+            let binding = binding.trim_matches('"');
+            if self.parse_key_combination(binding).is_none() {
+                error(key, &format!("unknown key \"{}\"", binding));
+            }
+        }
+
+        let speeds = [
+            ("initial_move_step", self.initial_move_step),
+            ("acceleration_base", self.acceleration_base),
+            ("acceleration_multiplier", self.acceleration_multiplier),
+            ("precision_divisor", self.precision_divisor),
+            ("hop_step", self.hop_step),
+        ];
+        for (field, value) in speeds {
+            if !value.is_finite() || value < 0.0 {
+                error(field, "must not be negative");
+            }
+        }
+        for (keys, line) in &self.bindings {
+            if self.parse_key_combination(keys).is_none() {
+                error("bindings", &format!("unknown key \"{}\"", keys));
+            }
+            if let Err(e) = parse_command(line) {
+                error("bindings", &format!("{}: {}", keys, e));
+            }
+        }
+        if let Err(e) = parse_button(&self.drag_button) {
+            error("drag_button", &format!("{} (left, right, middle, back, forward or button<n>)", e));
+        }
+        if !["drag", "anchor"].contains(&self.selection_style.as_str()) {
+            error("selection_style", &format!("unknown style \"{}\" (drag or anchor)", self.selection_style));
+        }
+        if let Err(e) = parse_button(&self.autoclick_button) {
+            error("autoclick_button", &e);
+        }
+        if self.autoclick_interval_ms == 0 {
+            error("autoclick_interval_ms", "must be greater than 0");
+        }
+        if self.autoclick_max_clicks == 0 {
+            error("autoclick_max_clicks", "must be greater than 0");
+        }
+        if self.paragraph_drag <= 0.0 {
+            error("paragraph_drag", "must be greater than 0");
+        }
+        for name in &self.drag_modifiers {
+            if parse_modifier(name).is_none() {
+                error("drag_modifiers", &format!("unknown modifier \"{}\" (ctrl, alt, shift or meta)", name));
+            }
+        }
+        if let Some(max) = self.max_move_step {
+            if !max.is_finite() || max <= 0.0 {
+                error("max_move_step", "must be greater than 0 (remove it for unlimited speed)");
+            }
+        }
+        if self.precision_divisor == 0.0 {
+            error("precision_divisor", "must be greater than 0");
+        }
+        if self.repeat_delay_ms == 0 {
+            error("repeat_delay_ms", "must be greater than 0");
+        }
+        if self.move_delay_ms == 0 {
+            error("move_delay_ms", "must be greater than 0");
+        }
+        if self.acceleration_multiplier > 0.0 && self.acceleration_base <= 1.0 {
+            error(
+                "acceleration_base",
+                "must be greater than 1 when acceleration_multiplier is set (set acceleration_multiplier = 0 for a constant speed)",
+            );
+        }
+
+        if self.click_gap_ms >= 250 {
+            diagnostics.push(ConfigDiagnostic::warning(
+                Some("click_gap_ms"),
+                "is long enough that double clicks may arrive as separate clicks".to_string(),
+            ));
+        }
+
+        diagnostics
+    }
+
+    /// Profile to apply on startup: the configured one, or "default" if it exists
+    fn startup_profile(&self) -> Option<String> {
+        self.profile.clone().or_else(|| {
+            self.profiles
+                .contains_key("default")
+                .then(|| "default".to_string())
+        })
+    }
+
+    /// Return a copy of this config with a profile's overrides applied
+    fn with_profile(&self, name: &str) -> Result<Self, String> {
+        let overrides = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("Unknown profile: {}", name))?;
+        self.with_overrides(overrides, &format!("profiles.{}", name))
+    }
+
+    /// Return a copy of this config with several fields replaced.
+    /// `context` names where the overrides came from in error messages.
+    fn with_overrides(&self, overrides: &toml::Table, context: &str) -> Result<Self, String> {
+        let mut table = toml::Value::try_from(self).map_err(|e| e.to_string())?;
+        let fields = table
+            .as_table_mut()
+            .ok_or_else(|| "Config is not a table".to_string())?;
+        for (key, value) in overrides {
+            // Unknown keys are reported as warnings by the loader
+            if CONFIG_KEYS.contains(&key.as_str()) {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+
+        let updated: Self = table
+            .try_into()
+            .map_err(|e| format!("{}: {}", context, e))?;
+        match updated.validate().into_iter().find(ConfigDiagnostic::is_error) {
+            Some(diagnostic) => Err(format!(
+                "{}.{}: {}",
+                context,
+                diagnostic.field.unwrap_or_default(),
+                diagnostic.message
+            )),
+            None => Ok(updated),
+        }
+    }
+
+    /// The profile after `current` in alphabetical order, wrapping around
+    fn next_profile(&self, current: Option<&str>) -> Option<String> {
+        let mut names = self.profiles.keys();
+        let next = match current {
+            Some(current) => names.find(|name| name.as_str() > current),
+            None => None,
+        };
+        next.or_else(|| self.profiles.keys().next()).cloned()
+    }
+
+    /// Current value of a single field, formatted as TOML
+    fn get_value(&self, key: &str) -> Option<String> {
+        if !CONFIG_KEYS.contains(&key) {
+            return None;
+        }
+        let table = toml::Value::try_from(self).ok()?;
+        Some(match table.get(key) {
+            Some(value) => value.to_string(),
+            None => "none".to_string(), // Unset optional field
+        })
+    }
+
+    /// Return a copy of this config with a single field changed.
+    /// The value is parsed as TOML, so strings can be given without quotes.
+    fn with_value(&self, key: &str, value: &str) -> Result<Self, String> {
+        if !CONFIG_KEYS.contains(&key) {
+            return Err(format!("Unknown option: {}", key));
+        }
+
+        let mut table = toml::Value::try_from(self).map_err(|e| e.to_string())?;
+        let fields = table
+            .as_table_mut()
+            .ok_or_else(|| "Config is not a table".to_string())?;
+        match parse_toml_value(value) {
+            // Clearing an optional field such as max_move_step
+            toml::Value::String(s) if s == "none" => {
+                fields.remove(key);
+            }
+            parsed => {
+                fields.insert(key.to_string(), parsed);
+            }
+        }
+
+        let updated: Self = table
+            .try_into()
+            .map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+        match updated.validate().into_iter().find(ConfigDiagnostic::is_error) {
+            Some(diagnostic) => Err(diagnostic.to_string()),
+            None => Ok(updated),
+        }
+    }
+
+    /// Write a single top-level field back to the config file, keeping the rest of the
+    /// file intact, comments included. `value` is formatted like `get_value` does, so
+    /// `none` removes the field.
+    fn save_value(path: &Path, key: &str, value: &str) -> std::io::Result<()> {
+        let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut document: toml_edit::DocumentMut = contents
+            .parse()
+            .map_err(|e: toml_edit::TomlError| invalid(format!("{}: {}", path.display(), e)))?;
+
+        if value == "none" {
+            document.remove(key);
+        } else {
+            let mut new_value: toml_edit::Value = value
+                .parse()
+                .map_err(|e: toml_edit::TomlError| invalid(format!("{} = {}: {}", key, value, e)))?;
+            // Keep a trailing comment on the line being replaced
+            if let Some(old_value) = document.get(key).and_then(toml_edit::Item::as_value) {
+                *new_value.decor_mut() = old_value.decor().clone();
+            }
+            document.insert(key, toml_edit::Item::Value(new_value));
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, document.to_string())
+    }
+}
+
+/// Config file in the current directory, from before XDG discovery existed
+const LEGACY_CONFIG_FILE: &str = "vim_navigation_config.toml";
+
+/// Where a user config file lives: $XDG_CONFIG_HOME/vinav/config.toml or ~/.config/vinav/config.toml
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("vinav").join("config.toml"))
+}
+
+/// Every place a config file is looked for, in priority order
+fn config_search_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = user_config_path().into_iter().collect();
+
+    // System-wide locations from $XDG_CONFIG_DIRS, then /etc
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    for dir in config_dirs.split(':').filter(|dir| !dir.is_empty()) {
+        paths.push(Path::new(dir).join("vinav").join("config.toml"));
+    }
+    paths.push(PathBuf::from("/etc/vinav/config.toml"));
+
+    // Kept last so existing setups keep working when launched from their directory
+    paths.push(PathBuf::from(LEGACY_CONFIG_FILE));
+    paths
+}
+
+/// Resolve the config file to use. An explicit `--config` path must exist;
+/// otherwise the first existing file from the search path wins, or none at all.
+fn find_config_file(explicit: Option<PathBuf>) -> Result<Option<PathBuf>, ConfigError> {
+    if let Some(path) = explicit {
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+        return Err(ConfigError::Message(format!(
+            "Config file {} does not exist",
+            path.display()
+        )));
+    }
+    Ok(config_search_paths().into_iter().find(|path| path.is_file()))
+}
+
+/// Where macros are kept between runs: $XDG_STATE_HOME/vinav/state.toml,
+/// or ~/.local/state/vinav/state.toml when it is unset
+fn state_file_path() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+    Some(state_home.join("vinav").join("state.toml"))
+}
+
+/// Contents of the state file. Macros are lists of `:` command lines, so the
+/// file can be edited by hand and picked up with `:reload`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedState {
+    macros: BTreeMap<String, Vec<String>>,
+}
+
+impl SavedState {
+    fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SavedState::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Read the macro registers from the state file
+fn load_macros(path: &Path) -> BTreeMap<char, Vec<String>> {
+    match SavedState::load(path) {
+        Ok(state) => state
+            .macros
+            .into_iter()
+            .filter_map(|(name, actions)| match parse_register(&[name.as_str()]) {
+                Ok(register) => Some((register.to_ascii_lowercase(), actions)),
+                Err(e) => {
+                    warn!(target: "macro", "{}: macro {}: {}", path.display(), name, e);
+                    None
+                }
+            })
+            .collect(),
+        Err(e) => {
+            warn!(target: "macro", "Could not read macros: {}", e);
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Write the macro registers to the state file
+fn save_macros(path: &Path, macros: &BTreeMap<char, Vec<String>>) {
+    let state = SavedState {
+        macros: macros
+            .iter()
+            .map(|(register, actions)| (register.to_string(), actions.clone()))
+            .collect(),
+    };
+    if let Err(e) = state.save(path) {
+        warn!(target: "macro", "Could not save macros: {}", e);
+    }
+}
+
+/// Every field of VimNavConfig, in file order
+const CONFIG_KEYS: &[&str] = &[
+    "initial_move_step",
+    "max_move_step",
+    "acceleration_base",
+    "acceleration_multiplier",
+    "repeat_delay_ms",
+    "move_delay_ms",
+    "click_gap_ms",
+    "precision_divisor",
+    "hop_step",
+    "key_left",
+    "key_down",
+    "key_up",
+    "key_right",
+    "key_click",
+    "key_toggle_mode",
+    "key_right_click",
+    "key_double_click",
+    "key_triple_click",
+    "key_middle_click",
+    "key_back",
+    "key_forward",
+    "key_select_toggle",
+    "key_goto_top",
+    "key_goto_bottom",
+    "key_yank",
+    "key_paste",
+    "key_cycle_profile",
+    "key_macro_record",
+    "key_repeat",
+    "key_drag",
+    "key_drag_cancel",
+    "drag_button",
+    "drag_modifiers",
+    "drag_profile",
+    "key_autoclick",
+    "key_autoclick_stop",
+    "autoclick_button",
+    "autoclick_interval_ms",
+    "autoclick_max_clicks",
+    "key_text_object",
+    "key_object_word",
+    "key_object_line",
+    "key_object_paragraph",
+    "paragraph_drag",
+    "key_cut",
+    "key_select_cancel",
+    "key_select_line",
+    "selection_style",
+];
+
+/// Top-level config entries that are sections rather than plain options
+const SECTION_KEYS: &[&str] = &["profile", "profiles", "app_rules", "hooks", "bindings"];
+
+/// A problem found while loading the config
+#[derive(Debug, Clone)]
+struct ConfigDiagnostic {
+    severity: Severity,
+    file: Option<String>,
+    line: Option<usize>,
+    field: Option<String>,
+    message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl ConfigDiagnostic {
+    fn error(field: Option<&str>, message: String) -> Self {
+        ConfigDiagnostic {
+            severity: Severity::Error,
+            file: None,
+            line: None,
+            field: field.map(str::to_string),
+            message,
+        }
+    }
+
+    fn warning(field: Option<&str>, message: String) -> Self {
+        ConfigDiagnostic {
+            severity: Severity::Warning,
+            ..Self::error(field, message)
+        }
+    }
+
+    fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Formatted like compiler output: file:line: severity: field: message
+        write!(f, "{}", self.file.as_deref().unwrap_or("config"))?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        match self.severity {
+            Severity::Error => write!(f, ": error: ")?,
+            Severity::Warning => write!(f, ": warning: ")?,
+        }
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// 1-based line number of a byte offset
+fn line_of_offset(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// 1-based line number where a top-level field is assigned
+fn find_field_line(text: &str, field: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| {
+            line.split_once('=')
+                .map(|(name, _)| name.trim() == field)
+                .unwrap_or(false)
+        })
+        .map(|index| index + 1)
+}
+
+/// 1-based line number of a `[section]` header
+fn find_section_line(text: &str, section: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| line.trim().trim_start_matches('[').trim_end_matches(']') == section)
+        .map(|index| index + 1)
+}
+
+/// 1-based line number of the nth (0-based) line equal to `needle`, e.g. a `[[table]]` header
+fn find_nth_line(text: &str, needle: &str, n: usize) -> Option<usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim() == needle)
+        .nth(n)
+        .map(|(index, _)| index + 1)
+}
+
+/// Suggest a known option for a misspelled one
+fn closest_config_key(unknown: &str) -> Option<&'static str> {
+    CONFIG_KEYS
+        .iter()
+        .map(|key| (edit_distance(unknown, key), *key))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, key)| key)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Check the config and report every problem, for `--check-config`
+fn check_config(path: Option<&Path>) -> bool {
+    let name = path
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "default config".to_string());
+    match VimNavConfig::load_checked(path) {
+        Ok((_, warnings)) => {
+            for warning in &warnings {
+                println!("{}", warning);
+            }
+            println!("{}: OK", name);
+            true
+        }
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+            eprintln!("{}: {} error(s)", name, errors);
+            false
+        }
+    }
+}
+
+/// Parse a value typed by the user as TOML, treating anything else as a bare string
+fn parse_toml_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
 }
 
 /// Custom error type for our application
@@ -261,6 +1343,8 @@ enum VimNavError {
     Grab(GrabError),
     Simulate(SimulateError),
     Config(ConfigError),
+    Command(String),
+    Io(std::io::Error),
 }
 
 impl std::fmt::Display for VimNavError {
@@ -270,6 +1354,8 @@ impl std::fmt::Display for VimNavError {
             VimNavError::Grab(e) => write!(f, "Grab error: {:?}", e),
             VimNavError::Simulate(e) => write!(f, "Simulate error: {:?}", e),
             VimNavError::Config(e) => write!(f, "Config error: {:?}", e),
+            VimNavError::Command(e) => write!(f, "{}", e),
+            VimNavError::Io(e) => write!(f, "IO error: {}", e),
         }
     }
 }
@@ -294,15 +1380,92 @@ impl From<SimulateError> for VimNavError {
     }
 }
 
+impl From<std::io::Error> for VimNavError {
+    fn from(err: std::io::Error) -> Self {
+        VimNavError::Io(err)
+    }
+}
+
 impl From<ConfigError> for VimNavError {
     fn from(err: ConfigError) -> Self {
         VimNavError::Config(err)
     }
 }
 
-/// Shared state for current cursor position and acceleration
-#[derive(Clone)]
-struct CursorState {
+/// What a register name typed next is for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PendingRegister {
+    Record,
+    Play,
+}
+
+/// What v is waiting for before it starts a selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PendingSelection {
+    Start,      // v was typed: key_text_object picks a text object, any other key selects as usual
+    TextObject, // vi was typed: waiting for w, l or p
+}
+
+/// Text under the cursor selected by viw, vil or vip
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextObject {
+    Word,
+    Line,
+    Paragraph,
+}
+
+impl TextObject {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "word" | "w" => Some(TextObject::Word),
+            "line" | "l" => Some(TextObject::Line),
+            "paragraph" | "p" => Some(TextObject::Paragraph),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TextObject::Word => "word",
+            TextObject::Line => "line",
+            TextObject::Paragraph => "paragraph",
+        }
+    }
+}
+
+/// Where an anchor selection started. Nothing is held while the cursor moves;
+/// ending the selection Shift+clicks at the cursor to select up to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SelectionAnchor {
+    x: f64,
+    y: f64,
+    linewise: bool, // V: widen both ends to whole lines
+}
+
+/// A mouse button held down by drag mode
+#[derive(Debug, Clone, PartialEq)]
+struct DragState {
+    button: Button,
+    modifiers: Vec<Key>,
+    /// Profile to go back to when the drag ends, if drag_profile replaced it
+    profile_before: Option<Option<String>>,
+}
+
+impl DragState {
+    /// Short description for status bars, like "ctrl+left"
+    fn label(&self) -> String {
+        self.modifiers
+            .iter()
+            .filter_map(|key| modifier_name(*key).map(str::to_string))
+            .chain([button_name(self.button)])
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+/// Shared state for current cursor position and acceleration
+#[derive(Clone)]
+struct CursorState {
     x: f64,
     y: f64,
     screen_width: f64,
@@ -317,14 +1480,48 @@ struct CursorState {
     cmd_pressed: bool,
     space_pressed: bool,    // For precision mode (100x slower)
     selection_active: bool, // For text selection mode
+    selection_anchor: Option<SelectionAnchor>, // Set while an anchor selection is active
+    drag: Option<DragState>, // Button held in drag mode
+    autoclick: Option<u64>,  // Id of the running autoclicker; clearing it stops the thread
+    next_autoclick: u64,
+    // Command line (Some while typing a `:` command)
+    command_line: Option<String>,
+    // Named cursor positions set with `:mark`
+    marks: HashMap<char, (f64, f64)>,
+    // Macros: registers of `:` command lines, recorded with q and played with @
+    count: Option<u32>,                       // Count typed before a command, like 5@a
+    pending_register: Option<PendingRegister>, // Waiting for a register name after q or @
+    pending_selection: Option<PendingSelection>, // Waiting for a text object after v
+    recording: Option<(char, Vec<String>)>,   // Register being recorded and what it holds so far
+    macro_anchor: Option<(f64, f64)>,          // Position before the movement not yet recorded
+    macros: BTreeMap<char, Vec<String>>,
+    last_macro: Option<char>, // Played again by @@
+    last_action: Option<(Action, u32)>, // Repeated by `.`, with the count it was done with
+    state_file: Option<PathBuf>, // Where macros are saved, None to keep them in memory
     // Configuration
     config: VimNavConfig,
+    config_path: Option<PathBuf>, // File the config was loaded from, if any
+    base_config: VimNavConfig,      // Config as loaded, before any profile is applied
+    set_values: BTreeMap<String, String>, // Changed with :set, applied on top of any profile
+    active_profile: Option<String>,
+    // Per-application rules (see AppRule)
+    app_rule: Option<usize>, // Index of the rule for the focused window
+    app_keymap: toml::Table, // Key binding overrides from that rule
+    profile_before_app_rule: Option<Option<String>>, // Restored when the window loses focus
+    typing_forced_by_app: bool, // Typing mode was entered because of a rule
+    focused_window: Option<FocusedWindow>,
+    keymap: Keymap,
 }
 
 impl CursorState {
-    fn new(config: VimNavConfig) -> Result<Self, VimNavError> {
+    fn new(config: VimNavConfig, config_path: Option<PathBuf>) -> Result<Self, VimNavError> {
         let (w, h) = display_size()?;
-        Ok(CursorState {
+        Ok(Self::with_screen(config, config_path, (w, h)))
+    }
+
+    /// State for a screen of the given size, e.g. the one a journal was recorded on
+    fn with_screen(config: VimNavConfig, config_path: Option<PathBuf>, (w, h): (u64, u64)) -> Self {
+        let mut state = CursorState {
             x: w as f64 / 2.0, // start in center
             y: h as f64 / 2.0,
             screen_width: w as f64,
@@ -337,16 +1534,223 @@ impl CursorState {
             cmd_pressed: false,
             space_pressed: false,
             selection_active: false,
-            config,
-        })
+            selection_anchor: None,
+            drag: None,
+            autoclick: None,
+            next_autoclick: 0,
+            command_line: None,
+            marks: HashMap::new(),
+            count: None,
+            pending_register: None,
+            pending_selection: None,
+            recording: None,
+            macro_anchor: None,
+            macros: BTreeMap::new(),
+            last_macro: None,
+            last_action: None,
+            state_file: None,
+            keymap: Keymap::from_config(&config),
+            config: config.clone(),
+            config_path,
+            active_profile: None,
+            base_config: config,
+            set_values: BTreeMap::new(),
+            app_rule: None,
+            app_keymap: toml::Table::new(),
+            profile_before_app_rule: None,
+            typing_forced_by_app: false,
+            focused_window: None,
+        };
+        let profile = state.base_config.startup_profile();
+        if let Err(e) = state.switch_profile(profile.as_deref()) {
+            error!(target: "config", "Failed to apply profile: {}", e);
+        }
+        state
+    }
+
+    /// Apply a profile on top of the base config, or no profile at all
+    fn switch_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        let previous = self.active_profile.clone();
+        self.apply_profile(name)?;
+        self.queue_profile_hook(previous);
+        Ok(())
+    }
+
+    fn apply_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        let previous = std::mem::replace(&mut self.active_profile, name.map(str::to_string));
+        if let Err(e) = self.refresh_config() {
+            self.active_profile = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Rebuild the running config from the base config, active profile, app
+    /// keymap and :set values, in that order
+    fn refresh_config(&mut self) -> Result<(), String> {
+        let mut config = match &self.active_profile {
+            Some(name) => self.base_config.with_profile(name)?,
+            None => self.base_config.clone(),
+        };
+        if !self.app_keymap.is_empty() {
+            config = config.with_overrides(&self.app_keymap, "app_rules.keymap")?;
+        }
+        for (key, value) in &self.set_values {
+            config = config.with_value(key, value)?;
+        }
+        self.apply_config(config);
+        Ok(())
+    }
+
+    /// Replace the base config, keeping the active profile if it still exists
+    fn replace_base_config(&mut self, base_config: VimNavConfig) -> Result<(), String> {
+        let previous_profile = self.active_profile.clone();
+        // Rule indexes may have changed, so let the focus watcher re-apply them
+        if let Some(profile) = self.profile_before_app_rule.take() {
+            self.active_profile = profile;
+        }
+        self.app_rule = None;
+        self.app_keymap.clear();
+
+        let profile = self
+            .active_profile
+            .clone()
+            .filter(|name| base_config.profiles.contains_key(name))
+            .or_else(|| base_config.startup_profile());
+        let previous = std::mem::replace(&mut self.base_config, base_config);
+        if let Err(e) = self.apply_profile(profile.as_deref()) {
+            self.base_config = previous;
+            return Err(e);
+        }
+        self.queue_profile_hook(previous_profile);
+        Ok(())
+    }
+
+    /// The hook for an event, if one is configured, with the state it happened in
+    fn hook(&self, event: &'static str, extra_env: Vec<(&'static str, String)>) -> Option<HookRun> {
+        let command = self.config.hooks.command(event)?.to_string();
+        let mut env = vec![
+            ("VINAV_EVENT", event.to_string()),
+            ("VINAV_PROFILE", self.active_profile.clone().unwrap_or_default()),
+            ("VINAV_X", format!("{:.0}", self.x)),
+            ("VINAV_Y", format!("{:.0}", self.y)),
+            (
+                "VINAV_WINDOW",
+                self.focused_window
+                    .as_ref()
+                    .map(|window| window.class.clone())
+                    .unwrap_or_default(),
+            ),
+        ];
+        env.extend(extra_env);
+        Some(HookRun { event, command, env })
+    }
+
+    /// Hand the hook for an event to the hook worker, so a slow hook never
+    /// holds up the thread that changed the state
+    fn queue_hook(&self, event: &'static str, extra_env: Vec<(&'static str, String)>) {
+        let Some(hook) = self.hook(event, extra_env) else {
+            return;
+        };
+        // Without a worker (like in a replay) hooks are not run at all
+        if let Some(queue) = HOOK_QUEUE.lock().unwrap().as_ref() {
+            let _ = queue.send(hook);
+        }
+    }
+
+    fn queue_mode_hooks(&self, previous: &str, mode: &str) {
+        if previous == mode {
+            return;
+        }
+        let exit_env = vec![("VINAV_MODE", previous.to_string()), ("VINAV_NEXT_MODE", mode.to_string())];
+        self.queue_hook("mode_exit", exit_env);
+        let enter_env = vec![("VINAV_MODE", mode.to_string()), ("VINAV_PREVIOUS_MODE", previous.to_string())];
+        self.queue_hook("mode_enter", enter_env);
+    }
+
+    fn queue_profile_hook(&self, previous: Option<String>) {
+        if previous != self.active_profile {
+            let env = vec![("VINAV_PREVIOUS_PROFILE", previous.unwrap_or_default())];
+            self.queue_hook("profile_switch", env);
+        }
+    }
+
+    /// Mark a text selection as started or ended, running its hook
+    fn set_selection_active(&mut self, active: bool) {
+        if self.selection_active != active {
+            self.selection_active = active;
+            self.queue_hook(if active { "selection_start" } else { "selection_end" }, Vec::new());
+        }
+    }
+
+    /// Swap in a new config without touching cursor position, modes or selection
+    fn apply_config(&mut self, config: VimNavConfig) {
+        self.keymap = Keymap::from_config(&config);
+        self.config = config;
+
+        // Forget held keys that are no longer movement keys, or they would never stop
+        let keymap = self.keymap.clone();
+        self.pressed_keys.retain(|key, _| keymap.is_movement_key(*key));
+        self.current_speeds.retain(|key, _| keymap.is_movement_key(*key));
     }
 
     fn start_key_press(&mut self, key: Key) {
-        self.pressed_keys.insert(key, Instant::now());
+        if self.recording.is_some() && self.macro_anchor.is_none() {
+            self.macro_anchor = Some((self.x, self.y));
+        }
+        self.pressed_keys.insert(key, now());
         self.current_speeds
             .insert(key, self.config.initial_move_step);
     }
 
+    /// Add an action to the macro being recorded, if any
+    fn record_action(&mut self, action: &Action) {
+        let Some(command) = action.to_command() else {
+            return;
+        };
+        self.flush_macro_movement();
+        if let Some((_, actions)) = &mut self.recording {
+            actions.push(command);
+        }
+    }
+
+    /// Record the cursor movement since the last recorded action as a relative move
+    fn flush_macro_movement(&mut self) {
+        let Some((x, y)) = self.macro_anchor.take() else {
+            return;
+        };
+        let (dx, dy) = ((self.x - x).round(), (self.y - y).round());
+        if let Some((_, actions)) = self.recording.as_mut().filter(|_| dx != 0.0 || dy != 0.0) {
+            actions.push(format!("move {} {}", dx, dy));
+        }
+    }
+
+    /// Start recording into a register, or stop and store the recording.
+    /// An uppercase register appends to the lowercase one, like in vim.
+    fn toggle_recording(&mut self, register: Option<char>) -> String {
+        self.flush_macro_movement();
+        if let Some((register, actions)) = self.recording.take() {
+            let message = format!("Recorded {} actions into register {}", actions.len(), register);
+            self.macros.insert(register, actions);
+            if let Some(path) = &self.state_file {
+                save_macros(path, &self.macros);
+            }
+            return message;
+        }
+        let Some(register) = register else {
+            return String::new();
+        };
+        let lower = register.to_ascii_lowercase();
+        let actions = if register.is_ascii_uppercase() {
+            self.macros.get(&lower).cloned().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        self.recording = Some((lower, actions));
+        self.macro_anchor = None;
+        format!("Recording @{}", lower)
+    }
+
     fn stop_key_press(&mut self, key: Key) {
         self.pressed_keys.remove(&key);
         self.current_speeds.remove(&key);
@@ -354,7 +1758,7 @@ impl CursorState {
 
     fn update_speed(&mut self, key: Key) -> f64 {
         if let Some(start_time) = self.pressed_keys.get(&key) {
-            let hold_duration = start_time.elapsed().as_secs_f64();
+            let hold_duration = now().duration_since(*start_time).as_secs_f64();
 
             // Fixed acceleration formula that actually uses the multiplier
             // Formula: speed = initial_move_step + (acceleration_multiplier * acceleration_base ^ hold_duration)
@@ -367,11 +1771,9 @@ impl CursorState {
                 new_speed /= 10.0;
             }
 
-            // Debug output to see what's happening
-            if hold_duration > 0.5 {
-                println!("DEBUG: hold_duration={:.2}s, exp_factor={:.2}, multiplier={:.1}, new_speed={:.2}, space_pressed={}", 
-                    hold_duration, exponential_factor, self.config.acceleration_multiplier, new_speed, self.space_pressed);
-            }
+            // Runs on every tick, so only shown with --log-level movement=trace
+            trace!(target: "movement", "hold_duration={:.2}s, exp_factor={:.2}, multiplier={:.1}, new_speed={:.2}, space_pressed={}",
+                hold_duration, exponential_factor, self.config.acceleration_multiplier, new_speed, self.space_pressed);
 
             // Apply max speed limit only if configured, otherwise unlimited
             let final_speed = match self.config.max_move_step {
@@ -411,16 +1813,220 @@ impl CursorState {
     }
 }
 
+/// Where synthesized input events go
+trait InputBackend: Send {
+    fn simulate(&mut self, event_type: &EventType) -> Result<(), SimulateError>;
+}
+
+/// Sends events to the OS through rdev
+struct RdevBackend;
+
+impl InputBackend for RdevBackend {
+    fn simulate(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        simulate(event_type)
+    }
+}
+
+/// Logs every event instead of sending it, so settings can be tried safely (--dry-run)
+struct DryRunBackend {
+    started: Instant,
+    x: f64,
+    y: f64,
+}
+
+impl DryRunBackend {
+    fn new() -> Self {
+        DryRunBackend {
+            started: Instant::now(),
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+}
+
+impl DryRunBackend {
+    /// The line logged for an event sent `elapsed` after the start, with the
+    /// cursor position it leaves behind
+    fn describe(&mut self, elapsed: Duration, event_type: &EventType) -> String {
+        if let EventType::MouseMove { x, y } = event_type {
+            self.x = *x;
+            self.y = *y;
+        }
+        format!(
+            "+{:.3}s {:?} cursor=({:.0}, {:.0})",
+            elapsed.as_secs_f64(),
+            event_type,
+            self.x,
+            self.y
+        )
+    }
+}
+
+impl InputBackend for DryRunBackend {
+    fn simulate(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let line = self.describe(self.started.elapsed(), event_type);
+        info!(target: "inject", "{}", line);
+        Ok(())
+    }
+}
+
+static INPUT_BACKEND: OnceLock<Mutex<Box<dyn InputBackend>>> = OnceLock::new();
+
+/// Replace the rdev backend. Only takes effect before the first event is sent.
+fn set_input_backend(backend: Box<dyn InputBackend>) {
+    if INPUT_BACKEND.set(Mutex::new(backend)).is_err() {
+        warn!(target: "inject", "Input backend already in use, not replacing it");
+    }
+}
+
+/// Replays set this so key hold times and movement ticks follow the journal, not the wall clock
+static VIRTUAL_CLOCK: OnceLock<Mutex<Instant>> = OnceLock::new();
+
+/// Whether this is a replay, which must not touch the config, the daemon or the process
+fn replaying() -> bool {
+    VIRTUAL_CLOCK.get().is_some()
+}
+
+/// Set by `:quit` during a replay, which ends the replay instead of the process
+static REPLAY_QUIT: AtomicBool = AtomicBool::new(false);
+
+/// The current time, virtual during replays
+fn now() -> Instant {
+    match VIRTUAL_CLOCK.get() {
+        Some(clock) => *clock.lock().unwrap(),
+        None => Instant::now(),
+    }
+}
+
+/// Records injected events with their virtual time, for replays
+struct ReplayBackend {
+    started: Instant,
+    injected: Arc<Mutex<Vec<String>>>,
+}
+
+impl InputBackend for ReplayBackend {
+    fn simulate(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let at_ms = now().duration_since(self.started).as_millis();
+        let line = format!("{}\t{}", at_ms, journal::format_event(event_type));
+        self.injected.lock().unwrap().push(line);
+        Ok(())
+    }
+}
+
+/// Send one event through the input backend
+fn inject(event_type: &EventType) -> Result<(), SimulateError> {
+    // Tracked while the backend is still locked, so release_held_inputs sees every press
+    let mut backend = input_backend().lock().unwrap_or_else(PoisonError::into_inner);
+    backend.simulate(event_type)?;
+    track_held_input(event_type);
+    Ok(())
+}
+
+fn input_backend() -> &'static Mutex<Box<dyn InputBackend>> {
+    INPUT_BACKEND.get_or_init(|| Mutex::new(Box::new(RdevBackend)))
+}
+
+/// A button or key pressed through inject and not released yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HeldInput {
+    Button(Button),
+    Key(Key),
+}
+
+/// Everything pressed through inject and not released yet, oldest first
+static HELD_INPUTS: Mutex<Vec<HeldInput>> = Mutex::new(Vec::new());
+
+fn track_held_input(event_type: &EventType) {
+    let (input, pressed) = match *event_type {
+        EventType::ButtonPress(button) => (HeldInput::Button(button), true),
+        EventType::ButtonRelease(button) => (HeldInput::Button(button), false),
+        EventType::KeyPress(key) => (HeldInput::Key(key), true),
+        EventType::KeyRelease(key) => (HeldInput::Key(key), false),
+        _ => return,
+    };
+    let mut held = HELD_INPUTS.lock().unwrap_or_else(PoisonError::into_inner);
+    held.retain(|other| *other != input);
+    if pressed {
+        held.push(input);
+    }
+}
+
+fn held_input_count() -> usize {
+    HELD_INPUTS.lock().unwrap_or_else(PoisonError::into_inner).len()
+}
+
+/// Release every button and key still held from inject, most recent first,
+/// so nothing stays logically pressed system-wide. The panic hook passes
+/// `wait = false`: the panicking thread may be the one holding the backend.
+fn release_held_inputs(wait: bool) {
+    if held_input_count() == 0 {
+        return;
+    }
+    let mut backend = if wait {
+        input_backend().lock().unwrap_or_else(PoisonError::into_inner)
+    } else {
+        match input_backend().try_lock() {
+            Ok(backend) => backend,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                // Left in the list for HeldInputGuard to release later
+                error!(target: "inject", "Input backend busy, could not release held inputs yet");
+                return;
+            }
+        }
+    };
+    let held = std::mem::take(&mut *HELD_INPUTS.lock().unwrap_or_else(PoisonError::into_inner));
+    for input in held.into_iter().rev() {
+        let release = match input {
+            HeldInput::Button(button) => EventType::ButtonRelease(button),
+            HeldInput::Key(key) => EventType::KeyRelease(key),
+        };
+        match backend.simulate(&release) {
+            Ok(()) => warn!(target: "inject", "Released {:?}, which was still held", input),
+            Err(e) => error!(target: "inject", "Failed to release {:?}: {:?}", input, e),
+        }
+    }
+}
+
+/// Release held inputs if the calling thread, the one that runs the grab, panics.
+/// A worker that panics leaves the daemon running, with selection and drag state
+/// that still expect the button held, so its panics release nothing.
+fn release_held_inputs_on_panic() {
+    let default_hook = std::panic::take_hook();
+    let main_thread = thread::current().id();
+    std::panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == main_thread {
+            release_held_inputs(false);
+        }
+        default_hook(info);
+    }));
+}
+
+/// Releases anything still held when run() returns, early or not
+struct HeldInputGuard;
+
+impl Drop for HeldInputGuard {
+    fn drop(&mut self) {
+        release_held_inputs(true);
+    }
+}
+
+/// Wait between injected events; replays on the virtual clock don't wait
+fn pause(ms: u64) {
+    if VIRTUAL_CLOCK.get().is_none() {
+        thread::sleep(Duration::from_millis(ms));
+    }
+}
+
 fn send_event(event_type: &EventType, config: &VimNavConfig) -> Result<(), SimulateError> {
-    let delay = Duration::from_millis(config.move_delay_ms);
-    match simulate(event_type) {
+    match inject(event_type) {
         Ok(()) => {
             // Let the OS catch up (especially important on macOS)
-            thread::sleep(delay);
+            pause(config.move_delay_ms);
             Ok(())
         }
         Err(e) => {
-            eprintln!("Failed to send event {:?}: {:?}", event_type, e);
+            warn!(target: "mouse", "Failed to send event {:?}: {:?}", event_type, e);
             Err(e)
         }
     }
@@ -435,11 +2041,19 @@ fn move_cursor(cursor_state: &Arc<Mutex<CursorState>>) -> Result<(), SimulateErr
     send_event(&EventType::MouseMove { x, y }, &config)
 }
 
-fn click_mouse(config: &VimNavConfig) -> Result<(), SimulateError> {
-    // Perform a left mouse click (press and release)
-    send_event(&EventType::ButtonPress(Button::Left), config)?;
-    send_event(&EventType::ButtonRelease(Button::Left), config)?;
-    println!("Mouse clicked!");
+/// Click a button `clicks` times in a row, close enough together for the OS
+/// to see a double or triple click
+fn click_mouse(button: Button, clicks: u32, config: &VimNavConfig) -> Result<(), SimulateError> {
+    for click in 0..clicks {
+        if click > 0 {
+            pause(config.click_gap_ms);
+        }
+        inject(&EventType::ButtonPress(button))?;
+        pause(config.click_gap_ms);
+        inject(&EventType::ButtonRelease(button))?;
+    }
+    pause(config.move_delay_ms);
+    debug!(target: "mouse", "{} click x{}", button_name(button), clicks);
     Ok(())
 }
 
@@ -495,30 +2109,357 @@ fn scroll(direction: &str, config: &VimNavConfig) -> Result<(), SimulateError> {
     Ok(())
 }
 
-fn right_click_mouse(config: &VimNavConfig) -> Result<(), SimulateError> {
-    // Perform a right mouse click (press and release)
-    send_event(&EventType::ButtonPress(Button::Right), config)?;
-    send_event(&EventType::ButtonRelease(Button::Right), config)?;
-    println!("Right mouse clicked!");
-    Ok(())
-}
-
-fn toggle_selection(cursor_state: &Arc<Mutex<CursorState>>) -> Result<(), SimulateError> {
+fn toggle_selection(cursor_state: &Arc<Mutex<CursorState>>, linewise: bool) -> Result<(), SimulateError> {
     let mut state = cursor_state.lock().unwrap();
-    state.selection_active = !state.selection_active;
+    let active = !state.selection_active;
+    state.set_selection_active(active);
+    let config = state.config.clone();
+    let cursor = (state.x, state.y);
 
-    if state.selection_active {
+    if let Some(anchor) = state.selection_anchor.take() {
+        // End an anchor selection by Shift+clicking where the cursor is now
+        drop(state);
+        select_from_anchor(anchor, cursor, &config)?;
+        info!(target: "mode", "Text selection ended");
+    } else if state.selection_active && (linewise || config.selection_style == "anchor") {
+        // Start an anchor selection by clicking, then move without holding anything
+        state.selection_anchor = Some(SelectionAnchor {
+            x: cursor.0,
+            y: cursor.1,
+            linewise,
+        });
+        drop(state);
+        click_mouse(Button::Left, 1, &config)?;
+        info!(target: "mode", "Text selection started{}", if linewise { " (line-wise)" } else { "" });
+    } else if state.selection_active {
         // Start selection by pressing left mouse button
-        simulate(&EventType::ButtonPress(Button::Left))?;
-        println!("Text selection started");
+        inject(&EventType::ButtonPress(Button::Left))?;
+        info!(target: "mode", "Text selection started");
     } else {
         // End selection by releasing left mouse button
-        simulate(&EventType::ButtonRelease(Button::Left))?;
-        println!("Text selection ended");
+        inject(&EventType::ButtonRelease(Button::Left))?;
+        info!(target: "mode", "Text selection ended");
+    }
+    Ok(())
+}
+
+/// End a text selection if there is one, keeping the text selected.
+/// Returns whether there was a selection to end.
+fn end_selection(cursor_state: &Arc<Mutex<CursorState>>) -> Result<bool, SimulateError> {
+    if !cursor_state.lock().unwrap().selection_active {
+        return Ok(false);
+    }
+    toggle_selection(cursor_state, false)?;
+    Ok(true)
+}
+
+/// Select from an anchor to the cursor with a Shift+click. A line-wise
+/// selection clicks the anchor again and moves it to the start of its line
+/// (or the end, when selecting upwards), then extends the other end to the
+/// end (or start) of the cursor's line.
+fn select_from_anchor(
+    anchor: SelectionAnchor,
+    (x, y): (f64, f64),
+    config: &VimNavConfig,
+) -> Result<(), SimulateError> {
+    let downwards = y >= anchor.y;
+    if anchor.linewise {
+        send_event(&EventType::MouseMove { x: anchor.x, y: anchor.y }, config)?;
+        click_mouse(Button::Left, 1, config)?;
+        press_line_edge(!downwards, false)?;
+        send_event(&EventType::MouseMove { x, y }, config)?;
+    }
+    inject(&EventType::KeyPress(Key::ShiftLeft))?;
+    click_mouse(Button::Left, 1, config)?;
+    inject(&EventType::KeyRelease(Key::ShiftLeft))?;
+    if anchor.linewise {
+        press_line_edge(downwards, true)?;
+    }
+    Ok(())
+}
+
+/// Move the caret to the start or end of its line, with Shift held to extend
+/// the selection. Sends Cmd+Left or Cmd+Right on macOS, Home or End elsewhere.
+fn press_line_edge(end: bool, extend: bool) -> Result<(), SimulateError> {
+    let (modifier, key) = if cfg!(target_os = "macos") {
+        (Some(Key::MetaLeft), if end { Key::RightArrow } else { Key::LeftArrow })
+    } else {
+        (None, if end { Key::End } else { Key::Home })
+    };
+    if let Some(modifier) = modifier {
+        inject(&EventType::KeyPress(modifier))?;
+    }
+    if extend {
+        inject(&EventType::KeyPress(Key::ShiftLeft))?;
+    }
+    inject(&EventType::KeyPress(key))?;
+    inject(&EventType::KeyRelease(key))?;
+    if extend {
+        inject(&EventType::KeyRelease(Key::ShiftLeft))?;
+    }
+    if let Some(modifier) = modifier {
+        inject(&EventType::KeyRelease(modifier))?;
+    }
+    Ok(())
+}
+
+/// A button pressed part way through a gesture, released again if the gesture
+/// fails before it gets to release it
+struct HeldButton(Button);
+
+impl HeldButton {
+    fn press(button: Button) -> Result<Self, SimulateError> {
+        inject(&EventType::ButtonPress(button))?;
+        Ok(HeldButton(button))
+    }
+
+    fn release(self) -> Result<(), SimulateError> {
+        let button = self.0;
+        std::mem::forget(self);
+        inject(&EventType::ButtonRelease(button))
+    }
+}
+
+impl Drop for HeldButton {
+    fn drop(&mut self) {
+        if let Err(e) = inject(&EventType::ButtonRelease(self.0)) {
+            error!(target: "mouse", "Failed to release {:?}: {:?}", self.0, e);
+        }
+    }
+}
+
+/// Select the text object under the cursor the way a user would with the
+/// mouse: a double click for a word, a triple click for a line, and a triple
+/// click held and dragged down by paragraph_drag for a paragraph. The button
+/// is released afterwards, so the selection stays for yank to copy.
+fn select_text_object(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    object: TextObject,
+    config: &VimNavConfig,
+) -> Result<(), VimNavError> {
+    {
+        let state = cursor_state.lock().unwrap();
+        if state.selection_active || state.drag.is_some() {
+            return Err(VimNavError::Command(
+                "End the selection or drag first".to_string(),
+            ));
+        }
+    }
+    match object {
+        TextObject::Word => click_mouse(Button::Left, 2, config)?,
+        TextObject::Line => click_mouse(Button::Left, 3, config)?,
+        TextObject::Paragraph => {
+            for _ in 0..2 {
+                inject(&EventType::ButtonPress(Button::Left))?;
+                pause(config.click_gap_ms);
+                inject(&EventType::ButtonRelease(Button::Left))?;
+                pause(config.click_gap_ms);
+            }
+            let held = HeldButton::press(Button::Left)?;
+            // Dragging with the third press held extends the selection line by line
+            let (start, bottom) = {
+                let state = cursor_state.lock().unwrap();
+                (state.y, state.screen_height - 1.0)
+            };
+            let steps = 4;
+            for step in 1..=steps {
+                let y = (start + config.paragraph_drag * step as f64 / steps as f64).min(bottom);
+                cursor_state.lock().unwrap().y = y;
+                move_cursor(cursor_state)?;
+                pause(config.click_gap_ms);
+            }
+            held.release()?;
+            pause(config.move_delay_ms);
+        }
     }
+    info!(target: "mode", "Selected the {} under the cursor", object.name());
     Ok(())
 }
 
+/// The back (X1) and forward (X2) side buttons. rdev reports them as
+/// Unknown with the X11 button number, or the XBUTTON number on Windows.
+#[cfg(not(target_os = "windows"))]
+const BACK_BUTTON: Button = Button::Unknown(8);
+#[cfg(not(target_os = "windows"))]
+const FORWARD_BUTTON: Button = Button::Unknown(9);
+#[cfg(target_os = "windows")]
+const BACK_BUTTON: Button = Button::Unknown(1);
+#[cfg(target_os = "windows")]
+const FORWARD_BUTTON: Button = Button::Unknown(2);
+
+/// rdev on macOS only sends the left and right buttons
+const SIDE_BUTTONS_UNSUPPORTED: &str = "The back and forward buttons can't be sent on macOS";
+
+/// Whether an action clicks, presses, releases, drags or autoclicks a side button
+fn uses_side_button(action: &Action) -> bool {
+    match action {
+        Action::Click { button, .. }
+        | Action::PressButton(button)
+        | Action::ReleaseButton(button)
+        | Action::StartDrag { button, .. }
+        | Action::AutoClick { button: Some(button), .. } => matches!(*button, BACK_BUTTON | FORWARD_BUTTON),
+        _ => false,
+    }
+}
+
+/// Parse a button name: left, right, middle, back (x1), forward (x2),
+/// or any other button by number as button<n>
+fn parse_button(name: &str) -> Result<Button, String> {
+    match name.to_lowercase().as_str() {
+        "left" => Ok(Button::Left),
+        "right" => Ok(Button::Right),
+        "middle" => Ok(Button::Middle),
+        "back" | "x1" | "forward" | "x2" if cfg!(target_os = "macos") => {
+            Err(SIDE_BUTTONS_UNSUPPORTED.to_string())
+        }
+        "back" | "x1" => Ok(BACK_BUTTON),
+        "forward" | "x2" => Ok(FORWARD_BUTTON),
+        other => other
+            .strip_prefix("button")
+            .and_then(|number| number.parse().ok())
+            .map(Button::Unknown)
+            .ok_or_else(|| format!("Unknown mouse button: {}", name)),
+    }
+}
+
+fn button_name(button: Button) -> String {
+    match button {
+        Button::Left => "left".to_string(),
+        Button::Right => "right".to_string(),
+        Button::Middle => "middle".to_string(),
+        BACK_BUTTON => "back".to_string(),
+        FORWARD_BUTTON => "forward".to_string(),
+        Button::Unknown(number) => format!("button{}", number),
+    }
+}
+
+fn parse_modifier(name: &str) -> Option<Key> {
+    match name.to_lowercase().as_str() {
+        "ctrl" | "control" => Some(Key::ControlLeft),
+        "alt" => Some(Key::Alt),
+        "shift" => Some(Key::ShiftLeft),
+        "meta" | "cmd" | "command" | "super" => Some(Key::MetaLeft),
+        _ => None,
+    }
+}
+
+fn modifier_name(key: Key) -> Option<&'static str> {
+    match key {
+        Key::ControlLeft => Some("ctrl"),
+        Key::Alt => Some("alt"),
+        Key::ShiftLeft => Some("shift"),
+        Key::MetaLeft => Some("meta"),
+        _ => None,
+    }
+}
+
+/// Hold modifiers and a mouse button down until `end_drag`
+fn start_drag(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    button: Button,
+    modifiers: &[Key],
+) -> Result<String, VimNavError> {
+    let mut state = cursor_state.lock().unwrap();
+    if state.drag.is_some() {
+        return Err(VimNavError::Command("Already dragging".to_string()));
+    }
+    if state.selection_active && state.selection_anchor.is_none() && button == Button::Left {
+        return Err(VimNavError::Command(
+            "The left button is held by the text selection".to_string(),
+        ));
+    }
+
+    // Switch to the drag profile for the length of the drag
+    let drag_profile = state
+        .config
+        .drag_profile
+        .clone()
+        .filter(|name| state.active_profile.as_ref() != Some(name));
+    let mut profile_before = None;
+    if let Some(name) = drag_profile {
+        let previous = state.active_profile.clone();
+        match state.switch_profile(Some(&name)) {
+            Ok(()) => profile_before = Some(previous),
+            Err(e) => warn!(target: "mode", "Drag profile not applied: {}", e),
+        }
+    }
+    let drag = DragState {
+        button,
+        modifiers: modifiers.to_vec(),
+        profile_before,
+    };
+    let label = drag.label();
+    state.drag = Some(drag);
+    drop(state);
+
+    for key in modifiers {
+        inject(&EventType::KeyPress(*key))?;
+    }
+    inject(&EventType::ButtonPress(button))?;
+    info!(target: "mode", "Drag started ({})", label);
+    Ok(format!("DRAG: {}", label))
+}
+
+/// Release the drag button and modifiers. Cancelling presses Escape first,
+/// which aborts the drag in most applications.
+fn end_drag(cursor_state: &Arc<Mutex<CursorState>>, cancel: bool) -> Result<String, VimNavError> {
+    let mut state = cursor_state.lock().unwrap();
+    let drag = state
+        .drag
+        .take()
+        .ok_or_else(|| VimNavError::Command("Not dragging".to_string()))?;
+    if let Some(profile) = &drag.profile_before {
+        if let Err(e) = state.switch_profile(profile.as_deref()) {
+            warn!(target: "mode", "Could not restore the profile after dragging: {}", e);
+        }
+    }
+    drop(state);
+
+    if cancel {
+        inject(&EventType::KeyPress(Key::Escape))?;
+        inject(&EventType::KeyRelease(Key::Escape))?;
+    }
+    inject(&EventType::ButtonRelease(drag.button))?;
+    for key in drag.modifiers.iter().rev() {
+        inject(&EventType::KeyRelease(*key))?;
+    }
+    let message = if cancel { "Drag cancelled" } else { "Drag ended" };
+    info!(target: "mode", "{}", message);
+    Ok(message.to_string())
+}
+
+/// Let go of any button held by drag or selection mode, and of every other
+/// button and key still pressed, e.g. before exiting
+fn release_mouse_buttons(cursor_state: &Arc<Mutex<CursorState>>) {
+    let (dragging, selecting) = {
+        let state = cursor_state.lock().unwrap();
+        (state.drag.is_some(), state.selection_active)
+    };
+    if dragging {
+        if let Err(e) = end_drag(cursor_state, false) {
+            error!(target: "mouse", "Failed to end drag: {}", e);
+        }
+    }
+    if selecting {
+        // An anchor selection holds nothing; drop it rather than Shift+click on the way out
+        let anchored = {
+            let mut state = cursor_state.lock().unwrap();
+            let anchored = state.selection_anchor.take().is_some();
+            if anchored {
+                state.set_selection_active(false);
+            }
+            anchored
+        };
+        if !anchored {
+            if let Err(e) = toggle_selection(cursor_state, false) {
+                error!(target: "mouse", "Failed to end selection: {:?}", e);
+            }
+        }
+    }
+    // Anything else, like a button from :press or a modifier left by a failed shortcut
+    release_held_inputs(true);
+}
+
 fn goto_screen_edge(
     cursor_state: &Arc<Mutex<CursorState>>,
     go_to_top: bool,
@@ -526,10 +2467,10 @@ fn goto_screen_edge(
     let mut state = cursor_state.lock().unwrap();
     if go_to_top {
         state.y = 0.0;
-        println!("Moved to top of screen");
+        debug!(target: "movement", "Moved to top of screen");
     } else {
         state.y = state.screen_height - 1.0;
-        println!("Moved to bottom of screen");
+        debug!(target: "movement", "Moved to bottom of screen");
     }
     drop(state);
 
@@ -546,44 +2487,2050 @@ fn goto_screen_edge(
 
 fn yank_copy() -> Result<(), SimulateError> {
     // Send Cmd+C (copy) on macOS
-    simulate(&EventType::KeyPress(Key::MetaLeft))?;
-    simulate(&EventType::KeyPress(Key::KeyC))?;
-    simulate(&EventType::KeyRelease(Key::KeyC))?;
-    simulate(&EventType::KeyRelease(Key::MetaLeft))?;
-    println!("Yanked (copied) to clipboard");
+    inject(&EventType::KeyPress(Key::MetaLeft))?;
+    inject(&EventType::KeyPress(Key::KeyC))?;
+    inject(&EventType::KeyRelease(Key::KeyC))?;
+    inject(&EventType::KeyRelease(Key::MetaLeft))?;
+    debug!(target: "clipboard", "Yanked (copied) to clipboard");
+    Ok(())
+}
+
+fn cut() -> Result<(), SimulateError> {
+    // Send Cmd+X (cut) on macOS
+    inject(&EventType::KeyPress(Key::MetaLeft))?;
+    inject(&EventType::KeyPress(Key::KeyX))?;
+    inject(&EventType::KeyRelease(Key::KeyX))?;
+    inject(&EventType::KeyRelease(Key::MetaLeft))?;
+    debug!(target: "clipboard", "Cut to clipboard");
     Ok(())
 }
 
 fn paste() -> Result<(), SimulateError> {
     // Send Cmd+V (paste) on macOS
-    simulate(&EventType::KeyPress(Key::MetaLeft))?;
-    simulate(&EventType::KeyPress(Key::KeyV))?;
-    simulate(&EventType::KeyRelease(Key::KeyV))?;
-    simulate(&EventType::KeyRelease(Key::MetaLeft))?;
-    println!("Pasted from clipboard");
+    inject(&EventType::KeyPress(Key::MetaLeft))?;
+    inject(&EventType::KeyPress(Key::KeyV))?;
+    inject(&EventType::KeyRelease(Key::KeyV))?;
+    inject(&EventType::KeyRelease(Key::MetaLeft))?;
+    debug!(target: "clipboard", "Pasted from clipboard");
     Ok(())
 }
 
-fn main() -> Result<(), VimNavError> {
-    // Load configuration
-    let config = VimNavConfig::load()?;
-    config.print_config();
+/// Actions that can be triggered from the `:` command line
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Goto { x: f64, y: f64 },
+    MoveBy { dx: f64, dy: f64 },
+    Scroll(String),
+    /// Click a button, `clicks` times in a row for a double or triple click
+    Click { button: Button, clicks: u32 },
+    /// Hold a button down until ReleaseButton
+    PressButton(Button),
+    ReleaseButton(Button),
+    SetNavigation(bool),
+    SetMark(char),
+    JumpToMark(char),
+    /// Move to the top or bottom edge of the screen
+    ScreenEdge { top: bool },
+    /// Start or end a text selection in the configured selection_style
+    ToggleSelection,
+    /// Start or end a line-wise anchor selection, like V in vim
+    ToggleLineSelection,
+    /// Select the word, line or paragraph under the cursor with a multi-click
+    SelectObject(TextObject),
+    /// Hold a button, and optionally modifiers, while the cursor moves
+    StartDrag { button: Button, modifiers: Vec<Key> },
+    /// Release the drag button, pressing Escape first when cancelling
+    EndDrag { cancel: bool },
+    /// Click a button on a timer until `clicks` is reached, the autoclicker
+    /// is stopped or navigation mode is left. Missing values come from the config.
+    AutoClick {
+        button: Option<Button>,
+        interval_ms: Option<u64>,
+        clicks: Option<u32>,
+    },
+    StopAutoClick,
+    /// Copy; ends a text selection first, keeping the text selected
+    Yank,
+    /// Cut; ends a text selection first
+    Cut,
+    /// Paste; ends a text selection first, so the pasted text replaces it
+    Paste,
+    /// End a text selection and click to clear it, like Esc in visual mode
+    CancelSelection,
+    /// Play the actions in a macro register `count` times
+    PlayMacro { register: char, count: u32 },
+    /// Show one macro register, or all of them
+    ShowMacro(Option<char>),
+    /// Show one config value, or all of them when no key is given
+    ShowSetting(Option<String>),
+    /// Change a config value, optionally writing it back to the config file
+    Set { key: String, value: String, save: bool },
+    /// Re-read the config file
+    Reload,
+    /// Switch to a named profile, or list profiles when no name is given
+    Profile(Option<String>),
+    /// Switch to the next profile in alphabetical order
+    NextProfile,
+    /// Show mode, cursor position, profile and selection
+    Status,
+    Help,
+    Quit,
+}
 
-    println!("Vim-style navigation with configurable keys started!");
-    println!();
-    println!("=== CONTROLS ===");
-    println!("VIM NAVIGATION MODE:");
-    println!("  {} - move cursor left", config.key_left);
-    println!("  {} - move cursor down", config.key_down);
-    println!("  {} - move cursor up", config.key_up);
-    println!("  {} - move cursor right", config.key_right);
-    println!("  {} - left mouse click", config.key_click);
+impl Action {
+    /// The command line that repeats this action, for actions a macro can hold
+    fn to_command(&self) -> Option<String> {
+        Some(match self {
+            Action::Goto { x, y } => format!("goto {} {}", x, y),
+            Action::MoveBy { dx, dy } => format!("move {} {}", dx, dy),
+            Action::Scroll(direction) => format!("scroll {}", direction),
+            Action::Click { button: Button::Left, clicks: 1 } => "click".to_string(),
+            Action::Click { button, clicks: 1 } => format!("click {}", button_name(*button)),
+            Action::Click { button, clicks } => format!("click {} {}", button_name(*button), clicks),
+            Action::PressButton(button) => format!("press {}", button_name(*button)),
+            Action::ReleaseButton(button) => format!("release {}", button_name(*button)),
+            Action::StartDrag { button, modifiers } => ["drag".to_string(), button_name(*button)]
+                .into_iter()
+                .chain(modifiers.iter().filter_map(|key| modifier_name(*key).map(str::to_string)))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Action::EndDrag { cancel: false } => "drop".to_string(),
+            Action::EndDrag { cancel: true } => "drop cancel".to_string(),
+            Action::SetMark(name) => format!("mark {}", name),
+            Action::JumpToMark(name) => format!("jump {}", name),
+            Action::ScreenEdge { top: true } => "top".to_string(),
+            Action::ScreenEdge { top: false } => "bottom".to_string(),
+            Action::ToggleSelection => "select".to_string(),
+            Action::ToggleLineSelection => "select linewise".to_string(),
+            Action::SelectObject(object) => format!("select {}", object.name()),
+            Action::Yank => "yank".to_string(),
+            Action::Cut => "cut".to_string(),
+            Action::Paste => "paste".to_string(),
+            Action::CancelSelection => "select cancel".to_string(),
+            Action::PlayMacro { register, count: 1 } => format!("play {}", register),
+            Action::PlayMacro { register, count } => format!("play {} {}", register, count),
+            _ => return None,
+        })
+    }
+}
+
+/// A command that can be typed after `:` in navigation mode
+struct CommandSpec {
+    name: &'static str,
+    usage: &'static str,
+    parse: fn(&[&str]) -> Result<Action, String>,
+}
+
+/// Registry of all command line commands. Add new commands here.
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "goto",
+        usage: "goto <x> <y>",
+        parse: parse_goto_command,
+    },
+    CommandSpec {
+        name: "move",
+        usage: "move <dx> <dy>",
+        parse: parse_move_command,
+    },
+    CommandSpec {
+        name: "scroll",
+        usage: "scroll <up|down|left|right>",
+        parse: parse_scroll_command,
+    },
+    CommandSpec {
+        name: "click",
+        usage: "click [button] [clicks]  (left, right, middle, back, forward or button<n>)",
+        parse: parse_click_command,
+    },
+    CommandSpec {
+        name: "press",
+        usage: "press <button>  (hold it down until :release)",
+        parse: |args| parse_button_argument(args).map(Action::PressButton),
+    },
+    CommandSpec {
+        name: "release",
+        usage: "release <button>",
+        parse: |args| parse_button_argument(args).map(Action::ReleaseButton),
+    },
+    CommandSpec {
+        name: "mode",
+        usage: "mode <nav|typing>",
+        parse: parse_mode_command,
+    },
+    CommandSpec {
+        name: "mark",
+        usage: "mark <a-z>",
+        parse: |args| parse_register(args).map(Action::SetMark),
+    },
+    CommandSpec {
+        name: "jump",
+        usage: "jump <a-z>",
+        parse: |args| parse_register(args).map(Action::JumpToMark),
+    },
+    CommandSpec {
+        name: "top",
+        usage: "top",
+        parse: |_| Ok(Action::ScreenEdge { top: true }),
+    },
+    CommandSpec {
+        name: "bottom",
+        usage: "bottom",
+        parse: |_| Ok(Action::ScreenEdge { top: false }),
+    },
+    CommandSpec {
+        name: "select",
+        usage: "select [word|line|paragraph|linewise|cancel]  (start or end a text selection, or select a text object)",
+        parse: |args| match args {
+            [] => Ok(Action::ToggleSelection),
+            ["cancel"] => Ok(Action::CancelSelection),
+            ["linewise"] => Ok(Action::ToggleLineSelection),
+            [name] => TextObject::parse(name)
+                .map(Action::SelectObject)
+                .ok_or_else(|| format!("Unknown text object: {} (expected word, line or paragraph)", name)),
+            _ => Err("Expected one text object".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "drag",
+        usage: "drag [button] [ctrl|alt|shift|meta]...",
+        parse: parse_drag_command,
+    },
+    CommandSpec {
+        name: "drop",
+        usage: "drop [cancel]  (end a drag, cancel presses Escape first)",
+        parse: |args| match args {
+            [] => Ok(Action::EndDrag { cancel: false }),
+            ["cancel"] => Ok(Action::EndDrag { cancel: true }),
+            _ => Err("Expected nothing or cancel".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "autoclick",
+        usage: "autoclick [button] [interval_ms] [clicks] | autoclick stop",
+        parse: parse_autoclick_command,
+    },
+    CommandSpec {
+        name: "yank",
+        usage: "yank",
+        parse: |_| Ok(Action::Yank),
+    },
+    CommandSpec {
+        name: "cut",
+        usage: "cut",
+        parse: |_| Ok(Action::Cut),
+    },
+    CommandSpec {
+        name: "paste",
+        usage: "paste",
+        parse: |_| Ok(Action::Paste),
+    },
+    CommandSpec {
+        name: "play",
+        usage: "play <a-z> [count]",
+        parse: parse_play_command,
+    },
+    CommandSpec {
+        name: "macro",
+        usage: "macro [a-z]",
+        parse: |args| match args {
+            [] => Ok(Action::ShowMacro(None)),
+            _ => parse_register(args).map(|register| Action::ShowMacro(Some(register))),
+        },
+    },
+    CommandSpec {
+        name: "set",
+        usage: "set [option[=value]]",
+        parse: |args| parse_set_command(args, false),
+    },
+    CommandSpec {
+        name: "set!",
+        usage: "set! option=value  (also saves it to the config file)",
+        parse: |args| parse_set_command(args, true),
+    },
+    CommandSpec {
+        name: "reload",
+        usage: "reload",
+        parse: |_| Ok(Action::Reload),
+    },
+    CommandSpec {
+        name: "profile",
+        usage: "profile [name]",
+        parse: |args| match args {
+            [] => Ok(Action::Profile(None)),
+            [name] => Ok(Action::Profile(Some(name.to_string()))),
+            _ => Err("Expected a single profile name".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "status",
+        usage: "status",
+        parse: |_| Ok(Action::Status),
+    },
+    CommandSpec {
+        name: "help",
+        usage: "help",
+        parse: |_| Ok(Action::Help),
+    },
+    CommandSpec {
+        name: "quit",
+        usage: "quit",
+        parse: |_| Ok(Action::Quit),
+    },
+];
+
+fn parse_goto_command(args: &[&str]) -> Result<Action, String> {
+    match args {
+        [x, y] => {
+            let x = x.parse().map_err(|_| format!("Invalid x coordinate: {}", x))?;
+            let y = y.parse().map_err(|_| format!("Invalid y coordinate: {}", y))?;
+            Ok(Action::Goto { x, y })
+        }
+        _ => Err("Expected two coordinates".to_string()),
+    }
+}
+
+fn parse_move_command(args: &[&str]) -> Result<Action, String> {
+    match args {
+        [dx, dy] => {
+            let dx = dx.parse().map_err(|_| format!("Invalid x offset: {}", dx))?;
+            let dy = dy.parse().map_err(|_| format!("Invalid y offset: {}", dy))?;
+            Ok(Action::MoveBy { dx, dy })
+        }
+        _ => Err("Expected two offsets".to_string()),
+    }
+}
+
+fn parse_scroll_command(args: &[&str]) -> Result<Action, String> {
+    match args {
+        [direction @ ("up" | "down" | "left" | "right")] => {
+            Ok(Action::Scroll(direction.to_string()))
+        }
+        _ => Err("Unknown scroll direction".to_string()),
+    }
+}
+
+fn parse_click_command(args: &[&str]) -> Result<Action, String> {
+    let (name, clicks) = match args {
+        [] => ("left", 1),
+        [name] => (*name, 1),
+        [name, clicks] => {
+            let clicks = clicks
+                .parse()
+                .ok()
+                .filter(|clicks| *clicks > 0)
+                .ok_or_else(|| format!("Invalid number of clicks: {}", clicks))?;
+            (*name, clicks)
+        }
+        _ => return Err("Expected a button and a number of clicks".to_string()),
+    };
+    let button = parse_button(name)?;
+    Ok(Action::Click { button, clicks })
+}
+
+fn parse_button_argument(args: &[&str]) -> Result<Button, String> {
+    match args {
+        [name] => parse_button(name),
+        _ => Err("Expected a single button".to_string()),
+    }
+}
+
+fn parse_autoclick_command(args: &[&str]) -> Result<Action, String> {
+    if args == ["stop"] {
+        return Ok(Action::StopAutoClick);
+    }
+    let (button, numbers) = match args.split_first() {
+        Some((name, rest)) if name.parse::<u64>().is_err() => (Some(parse_button(name)?), rest),
+        _ => (None, args),
+    };
+    let positive = |value: &str, what: &str| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|value| *value > 0)
+            .ok_or_else(|| format!("Invalid {}: {}", what, value))
+    };
+    let (interval_ms, clicks) = match numbers {
+        [] => (None, None),
+        [interval] => (Some(positive(interval, "interval")?), None),
+        [interval, clicks] => (
+            Some(positive(interval, "interval")?),
+            Some(positive(clicks, "number of clicks")?.min(u32::MAX as u64) as u32),
+        ),
+        _ => return Err("Expected a button, an interval and a number of clicks".to_string()),
+    };
+    Ok(Action::AutoClick {
+        button,
+        interval_ms,
+        clicks,
+    })
+}
+
+fn parse_drag_command(args: &[&str]) -> Result<Action, String> {
+    let (button, modifiers) = match args.split_first() {
+        Some((name, rest)) if parse_modifier(name).is_none() => (Some(parse_button(name)?), rest),
+        _ => (None, args),
+    };
+    let modifiers = modifiers
+        .iter()
+        .map(|name| parse_modifier(name).ok_or_else(|| format!("Unknown modifier: {}", name)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Action::StartDrag {
+        button: button.unwrap_or(Button::Left),
+        modifiers,
+    })
+}
+
+fn parse_play_command(args: &[&str]) -> Result<Action, String> {
+    let (register, count) = match args {
+        [register] => (parse_register(&[register])?, 1),
+        [register, count] => {
+            let count = count
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("Invalid count: {}", count))?;
+            (parse_register(&[register])?, count)
+        }
+        _ => return Err("Expected a register and an optional count".to_string()),
+    };
+    Ok(Action::PlayMacro {
+        register: register.to_ascii_lowercase(),
+        count,
+    })
+}
+
+fn parse_mode_command(args: &[&str]) -> Result<Action, String> {
+    match args {
+        ["nav"] | ["navigation"] => Ok(Action::SetNavigation(true)),
+        ["typing"] | ["type"] => Ok(Action::SetNavigation(false)),
+        _ => Err("Unknown mode".to_string()),
+    }
+}
+
+fn parse_set_command(args: &[&str], save: bool) -> Result<Action, String> {
+    let assignment = args.join(" ");
+    match assignment.split_once('=') {
+        Some((key, value)) => Ok(Action::Set {
+            key: key.trim().to_string(),
+            value: value.trim().to_string(),
+            save,
+        }),
+        None if save => Err("Expected option=value".to_string()),
+        None if assignment.is_empty() => Ok(Action::ShowSetting(None)),
+        None => Ok(Action::ShowSetting(Some(assignment))),
+    }
+}
+
+fn parse_register(args: &[&str]) -> Result<char, String> {
+    let mut chars = match args {
+        [name] => name.chars(),
+        _ => return Err("Expected a single register name".to_string()),
+    };
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Ok(c),
+        _ => Err("Register must be a single letter".to_string()),
+    }
+}
+
+/// Parse a command line such as "goto 640 400" into an action.
+/// Command names may be abbreviated to any unique prefix, like in vim (`:q`).
+fn parse_command(line: &str) -> Result<Action, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or_else(|| "Empty command".to_string())?;
+    let args: Vec<&str> = words.collect();
+
+    let spec = match COMMANDS.iter().find(|spec| spec.name == name) {
+        Some(spec) => spec,
+        None => {
+            let candidates: Vec<&CommandSpec> = COMMANDS
+                .iter()
+                .filter(|spec| spec.name.starts_with(name))
+                .collect();
+            match candidates.as_slice() {
+                [spec] => *spec,
+                [] => return Err(format!("Unknown command: {}", name)),
+                _ => return Err(format!("Ambiguous command: {}", name)),
+            }
+        }
+    };
+
+    (spec.parse)(&args).map_err(|e| format!("{} (usage: :{})", e, spec.usage))
+}
+
+/// Re-read the config file and swap it in, returning the list of changed fields.
+/// On any error the running config is left untouched.
+fn reload_config(cursor_state: &Arc<Mutex<CursorState>>) -> Result<Vec<String>, String> {
+    let path = cursor_state.lock().unwrap().config_path.clone();
+    let new_config = VimNavConfig::try_load(path.as_deref())
+        .map_err(|e| format!("keeping the old config:\n{}", e))?;
+
+    let mut state = cursor_state.lock().unwrap();
+    let old_config = state.config.clone();
+    state.replace_base_config(new_config)?;
+    let changes = old_config.diff(&state.config);
+    let changed = changes.iter().map(|change| change.trim()).collect::<Vec<_>>().join("\n");
+    state.queue_hook("config_reload", vec![("VINAV_CHANGES", changed)]);
+    Ok(changes)
+}
+
+/// Watch the config file and reload it whenever it is modified
+fn spawn_config_watcher(cursor_state: Arc<Mutex<CursorState>>, running: Arc<Mutex<bool>>) {
+    // The path is looked up every time, since `:set!` may create the file later
+    let watched_file = move |cursor_state: &Arc<Mutex<CursorState>>| {
+        let path = cursor_state.lock().unwrap().config_path.clone()?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Some((path, modified))
+    };
+
+    thread::spawn(move || {
+        let mut last_seen = watched_file(&cursor_state);
+        while *running.lock().unwrap() {
+            thread::sleep(Duration::from_millis(500));
+
+            let seen = watched_file(&cursor_state);
+            if seen == last_seen {
+                continue;
+            }
+            last_seen = seen.clone();
+            let Some((path, _)) = seen else { continue };
+
+            let reloaded = reload_config(&cursor_state);
+            notify_status_watchers();
+            match reloaded {
+                Ok(changes) if changes.is_empty() => {}
+                Ok(changes) => {
+                    info!(target: "config", "{} changed, config reloaded:", path.display());
+                    for change in changes {
+                        info!(target: "config", "{}", change);
+                    }
+                }
+                Err(e) => error!(
+                    target: "config",
+                    "{} changed but could not be reloaded: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    });
+}
+
+/// The window that currently has keyboard focus
+#[derive(Debug, Clone, PartialEq, Default)]
+struct FocusedWindow {
+    instance: String,
+    class: String,
+    title: String,
+}
+
+/// Source of the currently focused window, polled by the focus watcher
+trait FocusedWindowProvider {
+    fn focused_window(&mut self) -> Option<FocusedWindow>;
+}
+
+/// Reads _NET_ACTIVE_WINDOW, WM_CLASS and the window title from the X server
+#[cfg(target_os = "linux")]
+struct X11FocusProvider {
+    connection: x11rb::rust_connection::RustConnection,
+    root: u32,
+    net_active_window: u32,
+    net_wm_name: u32,
+    utf8_string: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl X11FocusProvider {
+    fn connect() -> Option<Self> {
+        use x11rb::connection::Connection;
+        use x11rb::protocol::xproto::ConnectionExt;
+
+        let (connection, screen) = x11rb::connect(None).ok()?;
+        let root = connection.setup().roots.get(screen)?.root;
+        let atom = |name: &str| {
+            connection
+                .intern_atom(false, name.as_bytes())
+                .ok()?
+                .reply()
+                .ok()
+                .map(|reply| reply.atom)
+        };
+        let net_active_window = atom("_NET_ACTIVE_WINDOW")?;
+        let net_wm_name = atom("_NET_WM_NAME")?;
+        let utf8_string = atom("UTF8_STRING")?;
+        Some(X11FocusProvider {
+            connection,
+            root,
+            net_active_window,
+            net_wm_name,
+            utf8_string,
+        })
+    }
+
+    fn property(
+        &self,
+        window: u32,
+        property: u32,
+        kind: u32,
+    ) -> Option<x11rb::protocol::xproto::GetPropertyReply> {
+        use x11rb::protocol::xproto::ConnectionExt;
+        self.connection
+            .get_property(false, window, property, kind, 0, 1024)
+            .ok()?
+            .reply()
+            .ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl FocusedWindowProvider for X11FocusProvider {
+    fn focused_window(&mut self) -> Option<FocusedWindow> {
+        use x11rb::protocol::xproto::AtomEnum;
+
+        let window = self
+            .property(self.root, self.net_active_window, AtomEnum::WINDOW.into())?
+            .value32()?
+            .next()
+            .filter(|window| *window != 0)?;
+
+        // WM_CLASS holds two NUL-terminated strings: instance, then class
+        let wm_class = self
+            .property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())
+            .map(|reply| reply.value)
+            .unwrap_or_default();
+        let mut names = wm_class
+            .split(|byte| *byte == 0)
+            .map(|name| String::from_utf8_lossy(name).into_owned());
+        let instance = names.next().unwrap_or_default();
+        let class = names.next().unwrap_or_default();
+
+        // Prefer the UTF-8 EWMH title, fall back to the legacy WM_NAME
+        let title = self
+            .property(window, self.net_wm_name, self.utf8_string)
+            .filter(|reply| !reply.value.is_empty())
+            .or_else(|| self.property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()))
+            .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+            .unwrap_or_default();
+
+        Some(FocusedWindow {
+            instance,
+            class,
+            title,
+        })
+    }
+}
+
+/// Provider that reports whatever window it is told to, for tests and replays
+#[derive(Clone, Default)]
+struct MockFocusProvider {
+    window: Arc<Mutex<Option<FocusedWindow>>>,
+}
+
+impl MockFocusProvider {
+    fn focus(&self, window: Option<FocusedWindow>) {
+        *self.window.lock().unwrap() = window;
+    }
+}
+
+impl FocusedWindowProvider for MockFocusProvider {
+    fn focused_window(&mut self) -> Option<FocusedWindow> {
+        self.window.lock().unwrap().clone()
+    }
+}
+
+/// The focus provider for this platform, if one is available
+fn default_focus_provider() -> Option<Box<dyn FocusedWindowProvider>> {
+    #[cfg(target_os = "linux")]
+    if let Some(provider) = X11FocusProvider::connect() {
+        return Some(Box::new(provider));
+    }
+    None
+}
+
+/// Apply the first app rule matching the focused window, undoing the previous rule
+fn apply_app_rules(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    window: Option<FocusedWindow>,
+) {
+    let mut state = cursor_state.lock().unwrap();
+    let rule_index = window.as_ref().and_then(|window| {
+        state
+            .base_config
+            .app_rules
+            .iter()
+            .position(|rule| rule.matches(window))
+    });
+    state.focused_window = window;
+    if rule_index == state.app_rule {
+        return;
+    }
+    let rule = rule_index.map(|index| state.base_config.app_rules[index].clone());
+
+    // Undo the previous rule
+    state.app_rule = rule_index;
+    state.app_keymap.clear();
+    let mut profile = state.active_profile.clone();
+    if let Some(previous) = state.profile_before_app_rule.take() {
+        profile = previous;
+    }
+
+    // Apply the new one
+    if let Some(rule) = &rule {
+        state.app_keymap = rule.keymap.clone();
+        if let Some(rule_profile) = &rule.profile {
+            state.profile_before_app_rule = Some(profile.clone());
+            profile = Some(rule_profile.clone());
+        }
+        let class = state.focused_window.as_ref().map(|window| window.class.as_str());
+        info!(
+            target: "focus",
+            "Window {} matched app rule: {}",
+            class.unwrap_or("?"),
+            rule.describe()
+        );
+    }
+    if let Err(e) = state.switch_profile(profile.as_deref()) {
+        error!(target: "focus", "Failed to apply app rule: {}", e);
+    }
+
+    let force_typing = rule.as_ref().map(|rule| rule.typing).unwrap_or(false);
+    let restore_navigation = state.typing_forced_by_app && !force_typing;
+    let nav_enabled = *navigation_enabled.lock().unwrap();
+    state.typing_forced_by_app = force_typing && (nav_enabled || state.typing_forced_by_app);
+    drop(state);
+
+    if force_typing && nav_enabled {
+        set_navigation_mode(navigation_enabled, cursor_state, false);
+    } else if restore_navigation && !nav_enabled {
+        set_navigation_mode(navigation_enabled, cursor_state, true);
+    }
+}
+
+/// Poll the focused window and apply app rules whenever it changes
+fn spawn_focus_watcher(
+    cursor_state: Arc<Mutex<CursorState>>,
+    navigation_enabled: Arc<Mutex<bool>>,
+    running: Arc<Mutex<bool>>,
+    recorder: Option<Arc<journal::Recorder>>,
+) {
+    thread::spawn(move || {
+        let Some(mut provider) = default_focus_provider() else {
+            if !cursor_state.lock().unwrap().base_config.app_rules.is_empty() {
+                warn!(target: "focus", "No focused window information available, app_rules are disabled");
+            }
+            return;
+        };
+        let mut last_window = None;
+        while *running.lock().unwrap() {
+            let window = provider.focused_window();
+            let changed = window != last_window;
+            if let Some(recorder) = recorder.as_ref().filter(|_| changed) {
+                let names = window
+                    .as_ref()
+                    .map(|window| (window.instance.as_str(), window.class.as_str()));
+                recorder.record_focus(names);
+            }
+            last_window = window.clone();
+            apply_app_rules(&cursor_state, &navigation_enabled, window);
+            if changed {
+                notify_status_watchers();
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    });
+}
+
+/// Switch between navigation and typing mode
+fn set_navigation_mode(
+    navigation_enabled: &Arc<Mutex<bool>>,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    enabled: bool,
+) {
+    let was_enabled = std::mem::replace(&mut *navigation_enabled.lock().unwrap(), enabled);
+    let previous_mode = mode_name(&cursor_state.lock().unwrap(), was_enabled);
+    if enabled {
+        match &cursor_state.lock().unwrap().active_profile {
+            Some(profile) => info!(target: "mode", "VIM NAVIGATION MODE - navigation enabled (profile: {})", profile),
+            None => info!(target: "mode", "VIM NAVIGATION MODE - navigation enabled"),
+        }
+    } else {
+        info!(target: "mode", "TYPING MODE - navigation disabled");
+        // Typing with a button or modifier still held would go wrong
+        release_mouse_buttons(cursor_state);
+        // Clear any pressed keys when entering typing mode
+        let mut state = cursor_state.lock().unwrap();
+        state.pressed_keys.clear();
+        state.current_speeds.clear();
+        state.count = None;
+        state.pending_register = None;
+        state.pending_selection = None;
+        if state.command_line.take().is_some() {
+            println!();
+        }
+        if state.autoclick.take().is_some() {
+            info!(target: "mouse", "Autoclick stopped");
+        }
+    }
+    let state = cursor_state.lock().unwrap();
+    state.queue_mode_hooks(previous_mode, mode_name(&state, enabled));
+}
+
+/// The mode shown in the status and passed to hooks
+fn mode_name(state: &CursorState, nav_enabled: bool) -> &'static str {
+    if state.command_line.is_some() {
+        "command"
+    } else if nav_enabled {
+        "navigation"
+    } else {
+        "typing"
+    }
+}
+
+/// Bumped every time something a status bar shows may have changed
+static STATUS_GENERATION: Mutex<u64> = Mutex::new(0);
+static STATUS_CHANGED: Condvar = Condvar::new();
+
+/// Wake the control clients watching the status. Called after key events and
+/// after anything that changes the state from another thread.
+fn notify_status_watchers() {
+    *STATUS_GENERATION.lock().unwrap() += 1;
+    STATUS_CHANGED.notify_all();
+}
+
+/// Block until notify_status_watchers is called after generation `seen`, and
+/// return the new generation
+fn wait_for_status_change(seen: u64) -> u64 {
+    let generation = STATUS_GENERATION.lock().unwrap();
+    *STATUS_CHANGED
+        .wait_while(generation, |generation| *generation == seen)
+        .unwrap()
+}
+
+/// Snapshot of the state reported by `:status` and the control socket
+fn current_status(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> control::Status {
+    let nav_enabled = *navigation_enabled.lock().unwrap();
+    let state = cursor_state.lock().unwrap();
+    control::Status {
+        mode: mode_name(&state, nav_enabled).to_string(),
+        x: state.x,
+        y: state.y,
+        profile: state.active_profile.clone(),
+        selection: state.selection_active,
+        pending: match (&state.command_line, state.pending_register) {
+            (Some(line), _) => format!(":{}", line),
+            (None, None) if state.pending_selection.is_some() => {
+                let config = &state.config;
+                match state.pending_selection {
+                    Some(PendingSelection::TextObject) => {
+                        format!("{}{}", config.key_select_toggle, config.key_text_object)
+                    }
+                    _ => config.key_select_toggle.clone(),
+                }
+            }
+            (None, pending) => {
+                let count = state.count.map(|count| count.to_string()).unwrap_or_default();
+                match pending {
+                    Some(PendingRegister::Record) => format!("{}{}", count, state.config.key_macro_record),
+                    Some(PendingRegister::Play) => format!("{}@", count),
+                    None => count,
+                }
+            }
+        },
+        recording: state.recording.as_ref().map(|(register, _)| register.to_string()),
+        drag: state.drag.as_ref().map(DragState::label),
+        autoclick: state.autoclick.is_some(),
+        window: state
+            .focused_window
+            .as_ref()
+            .map(|window| window.class.clone()),
+    }
+}
+
+/// A hook command ready to run
+struct HookRun {
+    event: &'static str,
+    command: String,
+    env: Vec<(&'static str, String)>,
+}
+
+/// Where state changes send their hooks, set once the hook worker runs
+static HOOK_QUEUE: Mutex<Option<std::sync::mpsc::Sender<HookRun>>> = Mutex::new(None);
+
+/// Longest the exit hook may hold up quitting
+const EXIT_HOOK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Start a hook command in the background. The returned channel is told when it ends.
+fn run_hook(hook: HookRun) -> Option<std::sync::mpsc::Receiver<()>> {
+    #[cfg(windows)]
+    let mut process = {
+        let mut process = std::process::Command::new("cmd");
+        process.args(["/C", &hook.command]);
+        process
+    };
+    #[cfg(not(windows))]
+    let mut process = {
+        let mut process = std::process::Command::new("sh");
+        process.args(["-c", &hook.command]);
+        process
+    };
+    process.envs(hook.env).stdin(std::process::Stdio::null());
+
+    let event = hook.event;
+    match process.spawn() {
+        Ok(mut child) => {
+            // Wait in the background so the process does not linger as a zombie
+            let (done, finished) = std::sync::mpsc::channel();
+            thread::spawn(move || {
+                match child.wait() {
+                    Ok(status) if !status.success() => warn!(target: "hooks", "{} hook failed: {}", event, status),
+                    Ok(_) => debug!(target: "hooks", "{} hook finished", event),
+                    Err(e) => warn!(target: "hooks", "{} hook failed: {}", event, e),
+                }
+                let _ = done.send(());
+            });
+            Some(finished)
+        }
+        Err(e) => {
+            error!(target: "hooks", "Failed to run {} hook: {}", event, e);
+            None
+        }
+    }
+}
+
+/// Run the hooks queued by mode, selection, profile and config changes, in order
+fn spawn_hook_worker() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    *HOOK_QUEUE.lock().unwrap() = Some(sender);
+    thread::spawn(move || {
+        for hook in receiver {
+            run_hook(hook);
+        }
+    });
+}
+
+/// Run the exit hook right away and give it a moment to finish, since the
+/// process is about to end
+fn run_exit_hook(cursor_state: &Arc<Mutex<CursorState>>) {
+    let hook = cursor_state
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .hook("exit", Vec::new());
+    let Some(finished) = hook.and_then(run_hook) else {
+        return;
+    };
+    if finished.recv_timeout(EXIT_HOOK_TIMEOUT).is_err() {
+        warn!(target: "hooks", "exit hook is still running, quitting anyway");
+    }
+}
+
+/// Answer one control socket request, using the same actions as the key bindings
+fn handle_request(
+    request: control::Request,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> control::Response {
+    use control::{Request, Response};
+
+    let action = match request {
+        Request::Status | Request::Watch => {
+            return Response {
+                ok: true,
+                status: Some(current_status(cursor_state, navigation_enabled)),
+                ..Default::default()
+            }
+        }
+        Request::SetMode { mode } => parse_mode_command(&[&mode]),
+        Request::MoveTo { x, y } => Ok(Action::Goto { x, y }),
+        Request::MoveBy { dx, dy } => Ok(Action::MoveBy { dx, dy }),
+        Request::Click { button, clicks } => {
+            let button = button.unwrap_or_else(|| "left".to_string());
+            let clicks = clicks.unwrap_or(1).to_string();
+            parse_click_command(&[&button, &clicks])
+        }
+        Request::Scroll { direction } => parse_scroll_command(&[&direction]),
+        Request::Command { line } => parse_command(&line),
+        Request::Reload => Ok(Action::Reload),
+        Request::Quit => Ok(Action::Quit),
+    };
+    let action = match action {
+        Ok(action) => action,
+        Err(e) => return Response::error(e),
+    };
+
+    let config = cursor_state.lock().unwrap().config.clone();
+    let result = execute_action(&action, cursor_state, navigation_enabled, &config);
+    notify_status_watchers();
+    match result {
+        Ok(message) => Response::message(message),
+        Err(e) => Response::error(e.to_string()),
+    }
+}
+
+/// Serve newline-delimited JSON requests from one client until it disconnects
+#[cfg(unix)]
+fn handle_control_client(
+    stream: std::os::unix::net::UnixStream,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<control::Request>(&line) {
+            // Answer before exiting so the client knows the request arrived
+            Ok(control::Request::Quit) => {
+                let goodbye = control::Response::message("Quitting".to_string());
+                writeln!(writer, "{}", serde_json::to_string(&goodbye)?)?;
+                handle_request(control::Request::Quit, cursor_state, navigation_enabled)
+            }
+            // The connection only carries status updates from here on
+            Ok(control::Request::Watch) => {
+                return watch_status(&mut writer, cursor_state, navigation_enabled)
+            }
+            Ok(request) => handle_request(request, cursor_state, navigation_enabled),
+            Err(e) => control::Response::error(format!("Invalid request: {}", e)),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+    }
+    Ok(())
+}
+
+/// Send a status response now and after every change a status bar would show,
+/// until the client goes away
+#[cfg(unix)]
+fn watch_status(
+    writer: &mut std::os::unix::net::UnixStream,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut last: Option<control::Status> = None;
+    // Read before the status, so a change made while sending is not missed
+    let mut seen = *STATUS_GENERATION.lock().unwrap();
+    loop {
+        let status = current_status(cursor_state, navigation_enabled);
+        if !last.as_ref().is_some_and(|last| last.same_indicator(&status)) {
+            let response = control::Response {
+                ok: true,
+                status: Some(status.clone()),
+                ..Default::default()
+            };
+            // Fails once the client disconnects, which ends the watch
+            match writeln!(writer, "{}", serde_json::to_string(&response)?) {
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+            last = Some(status);
+        }
+        seen = wait_for_status_change(seen);
+    }
+}
+
+/// Release held buttons and keys and exit on Ctrl+C, kill or a closed terminal,
+/// like :quit does
+#[cfg(unix)]
+fn spawn_signal_handler(cursor_state: Arc<Mutex<CursorState>>) -> std::io::Result<()> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!(target: "mode", "Received signal {}, quitting", signal);
+            // Straight from the injected inputs, which works even if a panic poisoned the state
+            release_held_inputs(true);
+            run_exit_hook(&cursor_state);
+            let _ = std::fs::remove_file(control::socket_path());
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+/// Listen on the control socket so other tools can drive the daemon
+#[cfg(unix)]
+fn spawn_control_server(
+    cursor_state: Arc<Mutex<CursorState>>,
+    navigation_enabled: Arc<Mutex<bool>>,
+) -> std::io::Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = control::socket_path();
+    control::create_socket_dir(&path)?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("{} is in use, is vim_navigation already running?", path.display()),
+            ));
+        }
+        // Left behind by a previous run that did not shut down cleanly
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!(target: "control", "Control socket error: {}", e);
+                    continue;
+                }
+            };
+            let cursor_state = Arc::clone(&cursor_state);
+            let navigation_enabled = Arc::clone(&navigation_enabled);
+            thread::spawn(move || {
+                if let Err(e) = handle_control_client(stream, &cursor_state, &navigation_enabled) {
+                    warn!(target: "control", "Control client error: {}", e);
+                }
+            });
+        }
+    });
+    Ok(path)
+}
+
+/// How deep macros may play other macros, so a macro playing itself stops
+const MAX_MACRO_DEPTH: usize = 10;
+
+/// Play the actions stored in a register. Stops early when navigation mode is left.
+fn play_macro(
+    register: char,
+    count: u32,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    config: &VimNavConfig,
+    depth: usize,
+) -> Result<String, VimNavError> {
+    let actions = {
+        let mut state = cursor_state.lock().unwrap();
+        let actions = state
+            .macros
+            .get(&register)
+            .cloned()
+            .ok_or_else(|| VimNavError::Command(format!("Register {} is empty", register)))?;
+        state.last_macro = Some(register);
+        actions
+    };
+    debug!(target: "macro", "Playing @{} {} times", register, count);
+
+    for _ in 0..count {
+        for line in &actions {
+            if !*navigation_enabled.lock().unwrap() {
+                return Ok(format!("Macro @{} stopped, navigation is off", register));
+            }
+            let error = |e: String| VimNavError::Command(format!("@{}: {}: {}", register, line, e));
+            let action = parse_command(line).map_err(error)?;
+            match action {
+                Action::PlayMacro { register: inner, count } => {
+                    if depth >= MAX_MACRO_DEPTH {
+                        return Err(error("macros nested too deeply".to_string()));
+                    }
+                    play_macro(inner, count, cursor_state, navigation_enabled, config, depth + 1)?;
+                }
+                action if action.to_command().is_some() => {
+                    execute_action(&action, cursor_state, navigation_enabled, config)?;
+                }
+                _ => return Err(error("not allowed in a macro".to_string())),
+            }
+        }
+    }
+    Ok(format!("Played @{} {} times", register, count))
+}
+
+/// Most clicks a count before a click binding makes; more is a job for autoclick
+const MAX_COUNTED_CLICKS: u32 = 3;
+
+/// The counted repeat running in the background, if any: its stop flag and thread
+static REPEAT_RUNNER: Mutex<Option<(Arc<AtomicBool>, thread::JoinHandle<()>)>> = Mutex::new(None);
+
+/// Stop the counted repeat still running, if any, and wait for the step it is on
+fn stop_repeat() {
+    let runner = REPEAT_RUNNER.lock().unwrap().take();
+    if let Some((stop, handle)) = runner {
+        stop.store(true, Ordering::SeqCst);
+        let _ = handle.join();
+    }
+}
+
+/// Run an action from a key binding `count` times and remember it for `.`.
+/// A repeat still running from an earlier binding is stopped first.
+fn run_binding(
+    action: Action,
+    count: u32,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    config: &VimNavConfig,
+) {
+    // A count before a click binding is the number of clicks: 2<CR> double clicks
+    let (to_run, repeat) = match action {
+        Action::Click { button, clicks: 1 } => {
+            if count > MAX_COUNTED_CLICKS {
+                warn!(
+                    target: "mouse",
+                    "Clicking {} times at most, use autoclick for more",
+                    MAX_COUNTED_CLICKS
+                );
+            }
+            let clicks = count.min(MAX_COUNTED_CLICKS);
+            (Action::Click { button, clicks }, 1)
+        }
+        _ => (action.clone(), count),
+    };
+    stop_repeat();
+    {
+        let mut state = cursor_state.lock().unwrap();
+        for _ in 0..repeat {
+            state.record_action(&to_run);
+        }
+        state.last_action = Some((action, count));
+    }
+    if repeat == 1 {
+        match execute_action(&to_run, cursor_state, navigation_enabled, config) {
+            Ok(message) if !message.is_empty() => debug!(target: "command", "{}", message),
+            Ok(_) => {}
+            Err(e) => error!(target: "command", "{}", e),
+        }
+        return;
+    }
+
+    // A large count like 9999J would hold up all input if run inside the grab callback
+    let cursor_state = Arc::clone(cursor_state);
+    let navigation_enabled = Arc::clone(navigation_enabled);
+    let config = config.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+    let runner = run_in_background(move || {
+        for _ in 0..repeat {
+            if stopped.load(Ordering::SeqCst) {
+                debug!(target: "command", "Repeat stopped by a newer command");
+                break;
+            }
+            if !*navigation_enabled.lock().unwrap() {
+                info!(target: "command", "Repeat stopped, navigation is off");
+                break;
+            }
+            match execute_action(&to_run, &cursor_state, &navigation_enabled, &config) {
+                Ok(message) if !message.is_empty() => debug!(target: "command", "{}", message),
+                Ok(_) => {}
+                Err(e) => {
+                    error!(target: "command", "{}", e);
+                    break;
+                }
+            }
+        }
+        notify_status_watchers();
+    });
+    if let Some(handle) = runner {
+        *REPEAT_RUNNER.lock().unwrap() = Some((stop, handle));
+    }
+}
+
+/// Run a long action such as a macro without holding up input handling. Replays run it right away
+/// so their output stays the same from run to run.
+fn spawn_action(
+    action: Action,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    config: &VimNavConfig,
+) {
+    let cursor_state = Arc::clone(cursor_state);
+    let navigation_enabled = Arc::clone(navigation_enabled);
+    let config = config.clone();
+    run_in_background(move || {
+        match execute_action(&action, &cursor_state, &navigation_enabled, &config) {
+            Ok(message) if !message.is_empty() => info!(target: "command", "{}", message),
+            Ok(_) => {}
+            Err(e) => warn!(target: "command", "{}", e),
+        }
+        notify_status_watchers();
+    });
+}
+
+/// Run on a thread of its own, or right away in replays on the virtual clock.
+/// Returns the thread, if one was started.
+fn run_in_background<F: FnOnce() + Send + 'static>(run: F) -> Option<thread::JoinHandle<()>> {
+    if replaying() {
+        run();
+        None
+    } else {
+        Some(thread::spawn(run))
+    }
+}
+
+/// Start clicking on a timer thread. Each tick checks that this run is still
+/// the active one and that navigation mode is on, so stopping takes effect
+/// before the next click.
+fn start_autoclick(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    button: Button,
+    interval_ms: u64,
+    clicks: Option<u32>,
+    config: &VimNavConfig,
+) -> Result<String, VimNavError> {
+    let cap = config.autoclick_max_clicks;
+    if clicks.is_some_and(|clicks| clicks > cap) {
+        warn!(target: "mouse", "Autoclick limited to autoclick_max_clicks ({})", cap);
+    }
+    let clicks = clicks.map_or(cap, |clicks| clicks.min(cap));
+
+    let id = {
+        let mut state = cursor_state.lock().unwrap();
+        if state.autoclick.is_some() {
+            return Err(VimNavError::Command("The autoclicker is already running".to_string()));
+        }
+        state.next_autoclick += 1;
+        state.autoclick = Some(state.next_autoclick);
+        state.next_autoclick
+    };
+
+    let cursor_state = Arc::clone(cursor_state);
+    let navigation_enabled = Arc::clone(navigation_enabled);
+    let config = config.clone();
+    run_in_background(move || {
+        let active = || {
+            *navigation_enabled.lock().unwrap() && cursor_state.lock().unwrap().autoclick == Some(id)
+        };
+        let mut clicked = 0;
+        while clicked < clicks {
+            if clicked > 0 {
+                pause(interval_ms);
+            }
+            if !active() {
+                return; // Stopped by the stop key, :autoclick stop or leaving navigation mode
+            }
+            if let Err(e) = click_mouse(button, 1, &config) {
+                error!(target: "mouse", "Autoclick failed: {:?}", e);
+                break;
+            }
+            clicked += 1;
+        }
+        let mut state = cursor_state.lock().unwrap();
+        if state.autoclick == Some(id) {
+            state.autoclick = None;
+            info!(target: "mouse", "Autoclick finished after {} clicks", clicked);
+        }
+        drop(state);
+        notify_status_watchers();
+    });
+    Ok(format!(
+        "Autoclicking {} every {} ms (up to {} clicks)",
+        button_name(button),
+        interval_ms,
+        clicks
+    ))
+}
+
+/// Run an action and return a short message describing what happened
+fn execute_action(
+    action: &Action,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+    config: &VimNavConfig,
+) -> Result<String, VimNavError> {
+    if cfg!(target_os = "macos") && uses_side_button(action) {
+        return Err(VimNavError::Command(SIDE_BUTTONS_UNSUPPORTED.to_string()));
+    }
+    match action {
+        Action::Goto { x, y } => {
+            let mut state = cursor_state.lock().unwrap();
+            state.x = x.clamp(0.0, state.screen_width - 1.0);
+            state.y = y.clamp(0.0, state.screen_height - 1.0);
+            let message = format!("Moved to ({:.0}, {:.0})", state.x, state.y);
+            drop(state);
+            move_cursor(cursor_state)?;
+            Ok(message)
+        }
+        Action::MoveBy { dx, dy } => {
+            let (x, y) = {
+                let state = cursor_state.lock().unwrap();
+                (state.x + dx, state.y + dy)
+            };
+            execute_action(
+                &Action::Goto { x, y },
+                cursor_state,
+                navigation_enabled,
+                config,
+            )
+        }
+        Action::Scroll(direction) => {
+            scroll(direction, config)?;
+            Ok(format!("Scrolled {}", direction))
+        }
+        Action::Click { button, clicks: 1 } => {
+            click_mouse(*button, 1, config)?;
+            Ok(format!("Clicked {} button", button_name(*button)))
+        }
+        Action::Click { button, clicks } => {
+            click_mouse(*button, *clicks, config)?;
+            Ok(format!("Clicked {} button {} times", button_name(*button), clicks))
+        }
+        Action::SetNavigation(enabled) => {
+            set_navigation_mode(navigation_enabled, cursor_state, *enabled);
+            Ok(String::new())
+        }
+        Action::SetMark(name) => {
+            let mut state = cursor_state.lock().unwrap();
+            let position = (state.x, state.y);
+            state.marks.insert(*name, position);
+            Ok(format!(
+                "Mark '{}' set at ({:.0}, {:.0})",
+                name, position.0, position.1
+            ))
+        }
+        Action::JumpToMark(name) => {
+            let position = cursor_state.lock().unwrap().marks.get(name).copied();
+            match position {
+                Some((x, y)) => execute_action(
+                    &Action::Goto { x, y },
+                    cursor_state,
+                    navigation_enabled,
+                    config,
+                ),
+                None => Ok(format!("Mark '{}' not set", name)),
+            }
+        }
+        Action::ScreenEdge { top } => {
+            goto_screen_edge(cursor_state, *top)?;
+            Ok(String::new())
+        }
+        Action::ToggleSelection => {
+            toggle_selection(cursor_state, false)?;
+            Ok(String::new())
+        }
+        Action::ToggleLineSelection => {
+            toggle_selection(cursor_state, true)?;
+            Ok(String::new())
+        }
+        Action::SelectObject(object) => {
+            select_text_object(cursor_state, *object, config)?;
+            Ok(format!("Selected {}", object.name()))
+        }
+        Action::PressButton(button) => {
+            send_event(&EventType::ButtonPress(*button), config)?;
+            Ok(format!("Holding {} button", button_name(*button)))
+        }
+        Action::ReleaseButton(button) => {
+            send_event(&EventType::ButtonRelease(*button), config)?;
+            Ok(format!("Released {} button", button_name(*button)))
+        }
+        Action::StartDrag { button, modifiers } => start_drag(cursor_state, *button, modifiers),
+        Action::EndDrag { cancel } => end_drag(cursor_state, *cancel),
+        Action::AutoClick {
+            button,
+            interval_ms,
+            clicks,
+        } => start_autoclick(
+            cursor_state,
+            navigation_enabled,
+            button.or_else(|| parse_button(&config.autoclick_button).ok()).unwrap_or(Button::Left),
+            interval_ms.unwrap_or(config.autoclick_interval_ms),
+            *clicks,
+            config,
+        ),
+        Action::StopAutoClick => match cursor_state.lock().unwrap().autoclick.take() {
+            Some(_) => Ok("Autoclick stopped".to_string()),
+            None => Err(VimNavError::Command("The autoclicker is not running".to_string())),
+        },
+        Action::Yank => {
+            end_selection(cursor_state)?;
+            yank_copy()?;
+            Ok(String::new())
+        }
+        Action::Cut => {
+            end_selection(cursor_state)?;
+            cut()?;
+            Ok(String::new())
+        }
+        Action::Paste => {
+            end_selection(cursor_state)?;
+            paste()?;
+            Ok(String::new())
+        }
+        Action::CancelSelection => {
+            {
+                // Nothing is selected yet in an anchor selection, so forget the anchor
+                let mut state = cursor_state.lock().unwrap();
+                if state.selection_anchor.take().is_some() {
+                    state.set_selection_active(false);
+                    info!(target: "mode", "Text selection ended");
+                    return Ok("Selection cancelled".to_string());
+                }
+            }
+            if !end_selection(cursor_state)? {
+                return Err(VimNavError::Command("No text selection to cancel".to_string()));
+            }
+            // Releasing keeps the text selected; a click where it ended clears it
+            click_mouse(Button::Left, 1, config)?;
+            Ok("Selection cancelled".to_string())
+        }
+        Action::PlayMacro { register, count } => play_macro(
+            *register,
+            *count,
+            cursor_state,
+            navigation_enabled,
+            config,
+            0,
+        ),
+        Action::ShowMacro(register) => {
+            let state = cursor_state.lock().unwrap();
+            let lines: Vec<String> = state
+                .macros
+                .iter()
+                .filter(|(name, _)| register.is_none_or(|register| register.eq_ignore_ascii_case(name)))
+                .map(|(name, actions)| format!("{}: {}", name, actions.join("; ")))
+                .collect();
+            if lines.is_empty() {
+                return Ok("No macros recorded".to_string());
+            }
+            Ok(lines.join("\n"))
+        }
+        Action::ShowSetting(key) => {
+            let config = cursor_state.lock().unwrap().config.clone();
+            match key {
+                Some(key) => config
+                    .get_value(key)
+                    .map(|value| format!("{}={}", key, value))
+                    .ok_or_else(|| VimNavError::Command(format!("Unknown option: {}", key))),
+                None => Ok(CONFIG_KEYS
+                    .iter()
+                    .filter_map(|key| config.get_value(key).map(|v| format!("{}={}", key, v)))
+                    .collect::<Vec<_>>()
+                    .join("\n")),
+            }
+        }
+        Action::Set { key, value, save } => {
+            let mut state = cursor_state.lock().unwrap();
+            // Kept so profile switches, app rules and reloads don't undo it
+            let previous = state.set_values.insert(key.clone(), value.clone());
+            if let Err(e) = state.refresh_config() {
+                match previous {
+                    Some(previous) => state.set_values.insert(key.clone(), previous),
+                    None => state.set_values.remove(key),
+                };
+                return Err(VimNavError::Command(e));
+            }
+            let new_value = state.config.get_value(key).unwrap_or_default();
+            drop(state);
+
+            if *save && replaying() {
+                return Err(VimNavError::Command(format!(
+                    "{}={} applied, but not saved during a replay",
+                    key, new_value
+                )));
+            }
+            if *save {
+                // Without a config file yet, create the user config
+                let path = {
+                    let mut state = cursor_state.lock().unwrap();
+                    if state.config_path.is_none() {
+                        state.config_path = user_config_path();
+                    }
+                    state.config_path.clone()
+                };
+                let path = path.ok_or_else(|| {
+                    VimNavError::Command("No config file location available".to_string())
+                })?;
+                VimNavConfig::save_value(&path, key, &new_value).map_err(|e| {
+                    VimNavError::Command(format!(
+                        "{}={} applied, but saving failed: {}",
+                        key, new_value, e
+                    ))
+                })?;
+                Ok(format!("{}={} (saved to {})", key, new_value, path.display()))
+            } else {
+                Ok(format!("{}={}", key, new_value))
+            }
+        }
+        Action::Reload => {
+            if replaying() {
+                return Err(VimNavError::Command("Not reloading the config during a replay".to_string()));
+            }
+            let changes = reload_config(cursor_state).map_err(VimNavError::Command)?;
+            // The state file may have been edited by hand
+            let mut state = cursor_state.lock().unwrap();
+            if let Some(path) = state.state_file.clone() {
+                state.macros = load_macros(&path);
+            }
+            drop(state);
+            if changes.is_empty() {
+                Ok("Config reloaded (no changes)".to_string())
+            } else {
+                Ok(format!("Config reloaded:\n{}", changes.join("\n")))
+            }
+        }
+        Action::Profile(None) => {
+            let state = cursor_state.lock().unwrap();
+            if state.base_config.profiles.is_empty() {
+                return Ok("No profiles defined".to_string());
+            }
+            Ok(state
+                .base_config
+                .profiles
+                .keys()
+                .map(|name| {
+                    let marker = if state.active_profile.as_ref() == Some(name) { "*" } else { " " };
+                    format!("{} {}", marker, name)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        Action::Profile(Some(name)) => {
+            let mut state = cursor_state.lock().unwrap();
+            state.switch_profile(Some(name)).map_err(VimNavError::Command)?;
+            Ok(format!("PROFILE: {}", name))
+        }
+        Action::NextProfile => {
+            let mut state = cursor_state.lock().unwrap();
+            let next = state
+                .base_config
+                .next_profile(state.active_profile.as_deref())
+                .ok_or_else(|| VimNavError::Command("No profiles defined".to_string()))?;
+            state.switch_profile(Some(&next)).map_err(VimNavError::Command)?;
+            Ok(format!("PROFILE: {}", next))
+        }
+        Action::Status => {
+            let status = current_status(cursor_state, navigation_enabled);
+            Ok(format!(
+                "mode: {}, cursor: ({:.0}, {:.0}), profile: {}, selection: {}, window: {}",
+                status.mode,
+                status.x,
+                status.y,
+                status.profile.as_deref().unwrap_or("none"),
+                if status.selection { "active" } else { "off" },
+                status.window.as_deref().unwrap_or("unknown"),
+            ))
+        }
+        Action::Help => Ok(COMMANDS
+            .iter()
+            .map(|spec| format!(":{}", spec.usage))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Action::Quit => {
+            info!(target: "command", "Quitting");
+            release_mouse_buttons(cursor_state);
+            if replaying() {
+                REPLAY_QUIT.store(true, Ordering::SeqCst);
+                return Ok("Quitting".to_string());
+            }
+            run_exit_hook(cursor_state);
+            #[cfg(unix)]
+            let _ = std::fs::remove_file(control::socket_path());
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Convert a key to the character it types on a US layout
+fn key_to_char(key: Key, shift: bool) -> Option<char> {
+    let (plain, shifted) = match key {
+        Key::KeyA => ('a', 'A'),
+        Key::KeyB => ('b', 'B'),
+        Key::KeyC => ('c', 'C'),
+        Key::KeyD => ('d', 'D'),
+        Key::KeyE => ('e', 'E'),
+        Key::KeyF => ('f', 'F'),
+        Key::KeyG => ('g', 'G'),
+        Key::KeyH => ('h', 'H'),
+        Key::KeyI => ('i', 'I'),
+        Key::KeyJ => ('j', 'J'),
+        Key::KeyK => ('k', 'K'),
+        Key::KeyL => ('l', 'L'),
+        Key::KeyM => ('m', 'M'),
+        Key::KeyN => ('n', 'N'),
+        Key::KeyO => ('o', 'O'),
+        Key::KeyP => ('p', 'P'),
+        Key::KeyQ => ('q', 'Q'),
+        Key::KeyR => ('r', 'R'),
+        Key::KeyS => ('s', 'S'),
+        Key::KeyT => ('t', 'T'),
+        Key::KeyU => ('u', 'U'),
+        Key::KeyV => ('v', 'V'),
+        Key::KeyW => ('w', 'W'),
+        Key::KeyX => ('x', 'X'),
+        Key::KeyY => ('y', 'Y'),
+        Key::KeyZ => ('z', 'Z'),
+        Key::Num1 => ('1', '!'),
+        Key::Num2 => ('2', '@'),
+        Key::Num3 => ('3', '#'),
+        Key::Num4 => ('4', '$'),
+        Key::Num5 => ('5', '%'),
+        Key::Num6 => ('6', '^'),
+        Key::Num7 => ('7', '&'),
+        Key::Num8 => ('8', '*'),
+        Key::Num9 => ('9', '('),
+        Key::Num0 => ('0', ')'),
+        Key::Minus => ('-', '_'),
+        Key::Equal => ('=', '+'),
+        Key::Dot => ('.', '>'),
+        Key::Comma => (',', '<'),
+        Key::Slash => ('/', '?'),
+        Key::SemiColon => (';', ':'),
+        Key::Quote => ('\'', '"'),
+        Key::Space => (' ', ' '),
+        _ => return None,
+    };
+    Some(if shift { shifted } else { plain })
+}
+
+/// Modifier keys always pass through so the OS never sees them stuck
+fn is_modifier_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::ShiftLeft
+            | Key::ShiftRight
+            | Key::ControlLeft
+            | Key::ControlRight
+            | Key::Alt
+            | Key::AltGr
+            | Key::MetaLeft
+            | Key::MetaRight
+    )
+}
+
+/// Redraw the command line in the terminal
+fn echo_command_line(buffer: &str) {
+    use std::io::Write;
+    print!("\r\x1b[K:{}", buffer);
+    let _ = std::io::stdout().flush();
+}
+
+/// Close the command line, which only opens in navigation mode
+fn close_command_line(state: &mut CursorState) {
+    state.command_line = None;
+    println!();
+    state.queue_mode_hooks("command", "navigation");
+}
+
+/// Handle a key press while the command line is open.
+/// Returns the finished command line when Enter is pressed.
+fn command_line_key(state: &mut CursorState, key: Key, name: Option<&str>) -> Option<String> {
+    let shift_pressed = state.shift_pressed;
+    let buffer = state.command_line.as_mut()?;
+    match key {
+        Key::Return | Key::KpReturn => {
+            let line = buffer.trim().to_string();
+            close_command_line(state);
+            return Some(line);
+        }
+        Key::Escape => {
+            close_command_line(state);
+            info!(target: "command", "Command cancelled");
+            return None;
+        }
+        Key::Backspace => {
+            if buffer.pop().is_none() {
+                // Backspace on an empty line closes it, like in vim
+                close_command_line(state);
+                return None;
+            }
+        }
+        _ => {
+            // Prefer the OS-provided character, fall back to a US layout mapping
+            let typed = name
+                .filter(|text| text.chars().all(|c| !c.is_control()))
+                .map(str::to_string)
+                .or_else(|| key_to_char(key, shift_pressed).map(String::from));
+            if let Some(text) = typed {
+                buffer.push_str(&text);
+            }
+        }
+    }
+    echo_command_line(buffer);
+    None
+}
+
+/// Subcommands of the vim_navigation binary
+#[derive(Debug, PartialEq)]
+enum CliCommand {
+    Run(RunOptions),
+    Replay {
+        journal: PathBuf,
+        expect: Option<PathBuf>,
+    },
+    CheckConfig,
+    DumpConfig,
+    PrintKeymap,
+    Version,
+    Help,
+}
+
+/// How to run vim navigation
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RunOptions {
+    foreground: bool,
+    verbose: bool,
+    dry_run: bool,
+    /// Write grabbed input to this journal file
+    record: Option<PathBuf>,
+    /// Keep keys typed in typing mode in the journal
+    record_typing: bool,
+}
+
+/// Parsed command line arguments
+#[derive(Debug)]
+struct Cli {
+    command: CliCommand,
+    config_path: Option<PathBuf>,
+    log: logging::LogOptions,
+}
+
+const USAGE: &str = "\
+Usage: vim_navigation [--config <path>] [COMMAND]
+
+Commands:
+  run            Start vim navigation in the background
+    -f, --foreground   Stay attached to the terminal
+    -n, --dry-run      Grab and interpret keys, but only log the mouse and key
+                       events that would be sent
+    -v, --verbose      Print the configuration and controls on startup and log
+                       debug messages (-vv also logs every movement tick)
+    -q, --quiet        Only log warnings and errors (-qq only errors)
+    --log-level <filter>
+                       Log level, optionally per target, e.g. info,movement=trace
+                       (targets: config, mode, movement, mouse, clipboard,
+                       command, control, focus, hooks, macro, input, inject)
+    --log-file <path>  Append log messages to a file instead of stderr
+    --log-json         Write log messages as JSON lines
+    --record <file>    Write every grabbed input event to a journal file
+    --record-typing    Also record keys typed in typing mode (left out by default)
+  replay <file>  Run a journal through the key handling without touching the
+                 real mouse, printing the events that would be sent
+    --expect <file>    Compare with earlier replay output instead, exit non-zero
+                       if anything differs
+  check-config   Validate the config file and exit non-zero on errors
+  dump-config    Print the effective configuration as TOML
+  print-keymap   Print every key binding
+  version        Print version and build information
+  help           Print this message
+
+Without a command, runs in the foreground with verbose output.
+
+Options:
+  --config <path>  Use this config file instead of searching for one
+
+The VINAV_LOG environment variable is used when --log-level is not given.";
+
+impl Cli {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut command = None;
+        let mut config_path = None;
+        let mut run = RunOptions::default();
+        let mut expect = None;
+        let mut log = logging::LogOptions::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" | "-c" => {
+                    let path = args.next().ok_or("--config needs a path")?;
+                    config_path = Some(PathBuf::from(path));
+                }
+                "-f" | "--foreground" => run.foreground = true,
+                "-n" | "--dry-run" => run.dry_run = true,
+                "-v" | "--verbose" => {
+                    run.verbose = true;
+                    log.verbosity = log.verbosity.saturating_add(1);
+                }
+                "-vv" => {
+                    run.verbose = true;
+                    log.verbosity = log.verbosity.saturating_add(2);
+                }
+                "-q" | "--quiet" => log.verbosity = log.verbosity.saturating_sub(1),
+                "-qq" => log.verbosity = log.verbosity.saturating_sub(2),
+                "--log-level" => {
+                    let filter = args.next().ok_or("--log-level needs a filter")?;
+                    logging::parse_filter(&filter)?;
+                    log.filter = Some(filter);
+                }
+                "--log-file" => {
+                    let path = args.next().ok_or("--log-file needs a path")?;
+                    log.file = Some(PathBuf::from(path));
+                }
+                "--log-json" => log.json = true,
+                "--record" => {
+                    let path = args.next().ok_or("--record needs a path")?;
+                    run.record = Some(PathBuf::from(path));
+                }
+                "--record-typing" => run.record_typing = true,
+                "--expect" => {
+                    let path = args.next().ok_or("--expect needs a path")?;
+                    expect = Some(PathBuf::from(path));
+                }
+                "-h" | "--help" => command = Some(CliCommand::Help),
+                // Flag form kept from before subcommands existed
+                "--check-config" => command = Some(CliCommand::CheckConfig),
+                _ if arg.starts_with("--config=") => {
+                    config_path = Some(PathBuf::from(&arg["--config=".len()..]));
+                }
+                _ if command.is_some() => return Err(format!("Unexpected argument: {}", arg)),
+                "run" => command = Some(CliCommand::Run(RunOptions::default())),
+                "replay" => {
+                    let journal = args.next().ok_or("replay needs a journal file")?;
+                    command = Some(CliCommand::Replay {
+                        journal: PathBuf::from(journal),
+                        expect: None,
+                    });
+                }
+                "check-config" => command = Some(CliCommand::CheckConfig),
+                "dump-config" => command = Some(CliCommand::DumpConfig),
+                "print-keymap" => command = Some(CliCommand::PrintKeymap),
+                "version" | "--version" | "-V" => command = Some(CliCommand::Version),
+                "help" => command = Some(CliCommand::Help),
+                _ => return Err(format!("Unknown command: {}", arg)),
+            }
+        }
+
+        let command = match command {
+            // Flags may come before or after `run`
+            Some(CliCommand::Run(_)) => CliCommand::Run(run),
+            Some(CliCommand::Replay { journal, .. }) => CliCommand::Replay { journal, expect },
+            Some(command) => command,
+            None => CliCommand::Run(RunOptions {
+                foreground: true,
+                verbose: true,
+                ..run
+            }),
+        };
+        Ok(Cli {
+            command,
+            config_path,
+            log,
+        })
+    }
+}
+
+fn print_version() {
+    println!("vim_navigation {}", env!("CARGO_PKG_VERSION"));
+    println!("commit: {}", env!("VINAV_GIT_COMMIT"));
+    println!(
+        "target: {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+    println!(
+        "profile: {}",
+        if cfg!(debug_assertions) { "debug" } else { "release" }
+    );
+}
+
+fn print_keymap(config: &VimNavConfig) {
+    let bindings = [
+        (config.key_left.as_str(), "move cursor left"),
+        (config.key_down.as_str(), "move cursor down"),
+        (config.key_up.as_str(), "move cursor up"),
+        (config.key_right.as_str(), "move cursor right"),
+        (config.key_click.as_str(), "left mouse click"),
+        (config.key_right_click.as_str(), "right mouse click"),
+        (config.key_double_click.as_str(), "double click"),
+        (config.key_triple_click.as_str(), "triple click"),
+        (config.key_middle_click.as_str(), "middle click"),
+        (config.key_back.as_str(), "back button"),
+        (config.key_forward.as_str(), "forward button"),
+        (config.key_select_toggle.as_str(), "toggle text selection"),
+        (config.key_select_line.as_str(), "toggle line-wise selection"),
+        (config.key_text_object.as_str(), "after v: text object (iw word, il line, ip paragraph)"),
+        (config.key_goto_top.as_str(), "go to top of screen"),
+        (config.key_goto_bottom.as_str(), "go to bottom of screen"),
+        (config.key_yank.as_str(), "yank/copy"),
+        (config.key_paste.as_str(), "paste"),
+        (config.key_cut.as_str(), "visual mode: cut the selection"),
+        (config.key_select_cancel.as_str(), "visual mode: clear the selection"),
+        (":", "command line"),
+        ("shift+<movement>", "scroll"),
+        ("space+<movement>", "precision movement"),
+        (config.key_cycle_profile.as_str(), "switch to the next profile"),
+        (config.key_drag.as_str(), "start or end a drag"),
+        (config.key_drag_cancel.as_str(), "cancel a drag (presses Escape, then releases)"),
+        (config.key_autoclick.as_str(), "start or stop the autoclicker ({count} clicks)"),
+        (config.key_autoclick_stop.as_str(), "stop the autoclicker"),
+        (config.key_repeat.as_str(), "repeat the last action"),
+        ("{count}<key>", "repeat an action, e.g. 3 shift+j scrolls 3 times, 5l hops"),
+        (config.key_macro_record.as_str(), "record a macro: q{register} ... q"),
+        ("{count}@{register}", "play a macro (@@ plays the last one again)"),
+        (config.key_toggle_mode.as_str(), "toggle navigation/typing mode"),
+    ];
+    for (binding, description) in bindings {
+        println!("{:<20} {}", binding, description);
+    }
+    for (binding, line) in &config.bindings {
+        println!("{:<20} :{}", binding, line);
+    }
+}
+
+fn print_controls(config: &VimNavConfig) {
+    println!("=== CONTROLS ===");
+    println!("VIM NAVIGATION MODE:");
+    println!("  {} - move cursor left", config.key_left);
+    println!("  {} - move cursor down", config.key_down);
+    println!("  {} - move cursor up", config.key_up);
+    println!("  {} - move cursor right", config.key_right);
+    println!("  {} - left mouse click", config.key_click);
     println!("  {} - right mouse click", config.key_right_click);
-    println!("  {} - toggle text selection", config.key_select_toggle);
+    println!(
+        "  {} / {} / {} - middle click / back / forward",
+        config.key_middle_click, config.key_back, config.key_forward
+    );
+    println!(
+        "  {} / {} - double / triple click (2{} also double clicks)",
+        config.key_double_click, config.key_triple_click, config.key_click
+    );
+    println!(
+        "  {} - toggle text selection ({} style)",
+        config.key_select_toggle, config.selection_style
+    );
+    println!("  {} - toggle line-wise selection", config.key_select_line);
+    println!(
+        "  {v}{i}{} / {v}{i}{} / {v}{i}{} - select the word / line / paragraph under the cursor",
+        config.key_object_word,
+        config.key_object_line,
+        config.key_object_paragraph,
+        v = config.key_select_toggle,
+        i = config.key_text_object
+    );
     println!("  {} - go to top of screen", config.key_goto_top);
     println!("  {} - go to bottom of screen", config.key_goto_bottom);
     println!("  {} - yank/copy", config.key_yank);
     println!("  {} - paste", config.key_paste);
+    println!(
+        "  while selecting: {} copies, {} cuts, {} pastes over the selection, {} cancels",
+        config.key_yank, config.key_cut, config.key_paste, config.key_select_cancel
+    );
+    println!("  : - command line (:help lists commands, Esc cancels)");
+    println!("  {} - switch to the next profile", config.key_cycle_profile);
+    println!(
+        "  {} - start or end a {} drag ({} cancels it)",
+        config.key_drag, config.drag_button, config.key_drag_cancel
+    );
+    println!(
+        "  {} - click {} every {} ms until {} (50{} clicks 50 times)",
+        config.key_autoclick,
+        config.autoclick_button,
+        config.autoclick_interval_ms,
+        config.key_autoclick_stop,
+        config.key_autoclick
+    );
+    println!("  {} - repeat the last action (3. repeats it 3 times)", config.key_repeat);
+    println!("  5l, 3 Shift+j - counts hop {:.0} px per count, or repeat an action", config.hop_step);
+    println!(
+        "  {}a ... {} - record a macro into register a, 5@a plays it 5 times",
+        config.key_macro_record, config.key_macro_record
+    );
     println!("  Shift+hjkl - scroll in respective directions");
     println!(
         "  Space+hjkl - precision mode ({:.0}x slower)",
@@ -601,19 +4548,763 @@ fn main() -> Result<(), VimNavError> {
     println!("BOTH MODES:");
     println!("  Ctrl+C - quit program");
     println!();
-    println!("=== STARTING IN VIM NAVIGATION MODE ===");
-    println!("Hold movement keys longer for exponential acceleration!");
-    println!();
+}
+
+/// Start a detached copy of this program that runs in the foreground
+fn spawn_background(
+    config_path: Option<&Path>,
+    options: &RunOptions,
+    log: &logging::LogOptions,
+) -> Result<(), VimNavError> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    if let Some(path) = config_path {
+        command.arg("--config").arg(path);
+    }
+    command.args(["run", "--foreground"]);
+    if options.dry_run {
+        command.arg("--dry-run");
+    }
+    if let Some(path) = &options.record {
+        command.arg("--record").arg(path);
+    }
+    if options.record_typing {
+        command.arg("--record-typing");
+    }
+    command.args(log.to_args());
+
+    // Errors after the fork, like a bad config or missing permissions, go to this file
+    let output = daemon_output_path().and_then(|path| {
+        std::fs::create_dir_all(path.parent()?).ok()?;
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&path).ok()?;
+        Some((path, file))
+    });
+    let (stdout, stderr, start) = match &output {
+        Some((_, file)) => (
+            std::process::Stdio::from(file.try_clone()?),
+            std::process::Stdio::from(file.try_clone()?),
+            file.metadata()?.len(),
+        ),
+        None => (std::process::Stdio::null(), std::process::Stdio::null(), 0),
+    };
+    let mut child = command
+        .stdin(std::process::Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .spawn()?;
+
+    // Startup errors end the daemon within moments; report them rather than claiming it started
+    thread::sleep(Duration::from_millis(DAEMON_STARTUP_CHECK_MS));
+    if let Some(status) = child.try_wait()? {
+        eprintln!("Vim navigation exited right after starting ({})", status);
+        if let Some((path, _)) = &output {
+            let written = std::fs::read(path).unwrap_or_default();
+            eprint!("{}", String::from_utf8_lossy(&written[(start as usize).min(written.len())..]));
+        }
+        std::process::exit(1);
+    }
+    println!("Vim navigation started in the background (pid {})", child.id());
+    if let Some((path, _)) = &output {
+        println!("Output goes to {}", path.display());
+    }
+    Ok(())
+}
+
+/// How long `run` waits to see whether the background daemon fails to start
+const DAEMON_STARTUP_CHECK_MS: u64 = 500;
+
+/// Where a background daemon's stdout and stderr go, next to the state file
+fn daemon_output_path() -> Option<PathBuf> {
+    Some(state_file_path()?.with_file_name("daemon.log"))
+}
+
+fn main() -> Result<(), VimNavError> {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!();
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match cli.command {
+        CliCommand::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        CliCommand::Version => {
+            print_version();
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config_path = find_config_file(cli.config_path)?;
+    match cli.command {
+        CliCommand::CheckConfig => {
+            std::process::exit(if check_config(config_path.as_deref()) { 0 } else { 1 });
+        }
+        CliCommand::DumpConfig => {
+            let config = VimNavConfig::load_quiet(config_path.as_deref())?;
+            if config.max_move_step.is_none() {
+                println!("# max_move_step is unset (unlimited speed)");
+            }
+            print!("{}", toml::to_string_pretty(&config).expect("config is valid TOML"));
+            Ok(())
+        }
+        CliCommand::PrintKeymap => {
+            let config = VimNavConfig::load_quiet(config_path.as_deref())?;
+            print_keymap(&config);
+            Ok(())
+        }
+        CliCommand::Run(options) if !options.foreground => {
+            spawn_background(config_path.as_deref(), &options, &cli.log)
+        }
+        CliCommand::Run(options) => {
+            if let Err(e) = logging::init(&cli.log) {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+            if options.dry_run {
+                set_input_backend(Box::new(DryRunBackend::new()));
+                info!(target: "inject", "Dry run: events are logged instead of sent");
+            }
+            run(config_path, &options)
+        }
+        CliCommand::Replay { journal, expect } => {
+            if let Err(e) = logging::init(&cli.log) {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+            let matched = replay(config_path.as_deref(), &journal, expect.as_deref())?;
+            std::process::exit(if matched { 0 } else { 1 });
+        }
+        CliCommand::Help | CliCommand::Version => unreachable!(),
+    }
+}
+
+/// Move the cursor for every held movement key, returning how long to wait until the next tick
+fn movement_tick(
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> u64 {
+    // Re-read the config every tick so `:set` takes effect immediately
+    let (repeat_delay_ms, keymap) = {
+        let state = cursor_state.lock().unwrap();
+        (state.config.repeat_delay_ms, state.keymap.clone())
+    };
+
+    // Only move if navigation is enabled
+    if *navigation_enabled.lock().unwrap() {
+        let mut state = cursor_state.lock().unwrap();
+        let mut moved = false;
+
+        let left_key = keymap.left;
+        let down_key = keymap.down;
+        let up_key = keymap.up;
+        let right_key = keymap.right;
+
+        if state.is_key_pressed(left_key) {
+            state.move_left(left_key);
+            moved = true;
+        }
+        if state.is_key_pressed(down_key) {
+            state.move_down(down_key);
+            moved = true;
+        }
+        if state.is_key_pressed(up_key) {
+            state.move_up(up_key);
+            moved = true;
+        }
+        if state.is_key_pressed(right_key) {
+            state.move_right(right_key);
+            moved = true;
+        }
+
+        if moved {
+            drop(state); // Release the lock before calling move_cursor
+            if let Err(e) = move_cursor(cursor_state) {
+                error!(target: "movement", "Failed to move cursor: {:?}", e);
+            }
+        }
+    }
+
+    repeat_delay_ms
+}
+
+/// Decide what to do with one grabbed event: pass it on (Some) or block it (None)
+fn handle_event(
+    event: Event,
+    cursor_state: &Arc<Mutex<CursorState>>,
+    navigation_enabled: &Arc<Mutex<bool>>,
+) -> Option<Event> {
+    let nav_enabled = *navigation_enabled.lock().unwrap();
+    // The running config lives in the cursor state so it can be swapped at any time
+    let (config_clone, keymap) = {
+        let state = cursor_state.lock().unwrap();
+        (state.config.clone(), state.keymap.clone())
+    };
+
+    match event.event_type {
+        EventType::KeyPress(key) => {
+            // Track modifier states
+            let mut state = cursor_state.lock().unwrap();
+            match key {
+                Key::ShiftLeft | Key::ShiftRight => state.shift_pressed = true,
+                Key::ControlLeft | Key::ControlRight => state.ctrl_pressed = true,
+                Key::Alt => state.alt_pressed = true,
+                Key::MetaLeft | Key::MetaRight => state.cmd_pressed = true,
+                Key::Space => state.space_pressed = true,
+                _ => {}
+            }
+            drop(state); // Release the lock
+
+            // While the command line is open, every non-modifier key goes into it
+            if cursor_state.lock().unwrap().command_line.is_some() {
+                if is_modifier_key(key) {
+                    return Some(event);
+                }
+                let line = command_line_key(
+                    &mut cursor_state.lock().unwrap(),
+                    key,
+                    event.name.as_deref(),
+                );
+                if let Some(line) = line.filter(|line| !line.is_empty()) {
+                    match parse_command(&line) {
+                        Ok(action @ Action::PlayMacro { .. }) => {
+                            cursor_state.lock().unwrap().record_action(&action);
+                            spawn_action(action, cursor_state, navigation_enabled, &config_clone);
+                        }
+                        Ok(action) => {
+                            let mut state = cursor_state.lock().unwrap();
+                            state.record_action(&action);
+                            if action.to_command().is_some() {
+                                state.last_action = Some((action.clone(), 1));
+                            }
+                            drop(state);
+                            match execute_action(&action, cursor_state, navigation_enabled, &config_clone) {
+                                Ok(message) if !message.is_empty() => info!(target: "command", "{}", message),
+                                Ok(_) => {}
+                                Err(e) => warn!(target: "command", "{}", e),
+                            }
+                        }
+                        Err(e) => warn!(target: "command", "{}", e),
+                    }
+                }
+                return None; // Block this key
+            }
+
+            // Get current modifier states for key combination checking
+            let state = cursor_state.lock().unwrap();
+            let shift_pressed = state.shift_pressed;
+            let ctrl_pressed = state.ctrl_pressed;
+            let alt_pressed = state.alt_pressed;
+            let cmd_pressed = state.cmd_pressed;
+            drop(state);
+
+            // Mode switching - check for key combination
+            if let Some(toggle_combo) = &keymap.toggle_mode {
+                if config_clone.matches_key_combination(toggle_combo, key, shift_pressed, ctrl_pressed, alt_pressed, cmd_pressed) {
+                    set_navigation_mode(navigation_enabled, cursor_state, !nav_enabled);
+                    return None; // Block this key
+                }
+            }
+
+            // Profile switching (only works in navigation mode)
+            if let Some(cycle_combo) = keymap.cycle_profile.as_ref().filter(|_| nav_enabled) {
+                if config_clone.matches_key_combination(cycle_combo, key, shift_pressed, ctrl_pressed, alt_pressed, cmd_pressed) {
+                    match execute_action(&Action::NextProfile, cursor_state, navigation_enabled, &config_clone) {
+                        Ok(message) => info!(target: "mode", "{}", message),
+                        Err(e) => error!(target: "mode", "{}", e),
+                    }
+                    return None; // Block this key
+                }
+            }
+
+            // Text objects: v waits for one more key, so viw, vil and vip can select
+            // with a multi-click; any other key but Esc starts the selection and is handled as usual
+            if nav_enabled && !is_modifier_key(key) {
+                let pending = cursor_state.lock().unwrap().pending_selection.take();
+                match pending {
+                    Some(PendingSelection::Start) if key == keymap.text_object => {
+                        cursor_state.lock().unwrap().pending_selection = Some(PendingSelection::TextObject);
+                        return None; // Block this key
+                    }
+                    Some(PendingSelection::TextObject) => {
+                        if let Some(object) = keymap.text_object(key) {
+                            run_binding(Action::SelectObject(object), 1, cursor_state, navigation_enabled, &config_clone);
+                        }
+                        // Any other key cancels, like Esc in vim
+                        return None; // Block this key
+                    }
+                    // Esc or a second v backs out before anything is pressed
+                    Some(PendingSelection::Start)
+                        if key == Key::Escape || key == keymap.select_cancel || key == keymap.select_toggle =>
+                    {
+                        info!(target: "mode", "Text selection cancelled");
+                        return None; // Block this key
+                    }
+                    Some(PendingSelection::Start) => {
+                        run_binding(Action::ToggleSelection, 1, cursor_state, navigation_enabled, &config_clone);
+                    }
+                    None => {}
+                }
+            }
+
+            // Macros: q{register} records, {count}@{register} plays (only in navigation mode)
+            if nav_enabled && !is_modifier_key(key) {
+                let mut state = cursor_state.lock().unwrap();
+                let typed = key_to_char(key, shift_pressed);
+                let is_at = event.name.as_deref() == Some("@") || typed == Some('@');
+
+                if let Some(pending) = state.pending_register.take() {
+                    let count = state.count.take().unwrap_or(1);
+                    let register = match pending {
+                        PendingRegister::Play if is_at => state.last_macro,
+                        _ => typed.filter(char::is_ascii_alphabetic),
+                    };
+                    match (pending, register) {
+                        (PendingRegister::Record, Some(register)) => {
+                            let message = state.toggle_recording(Some(register));
+                            info!(target: "macro", "{}", message);
+                        }
+                        (PendingRegister::Play, Some(register)) => {
+                            let action = Action::PlayMacro {
+                                register: register.to_ascii_lowercase(),
+                                count,
+                            };
+                            state.record_action(&action);
+                            drop(state);
+                            spawn_action(action, cursor_state, navigation_enabled, &config_clone);
+                        }
+                        // Any other key cancels, like Esc in vim
+                        (_, None) => {}
+                    }
+                    return None; // Block this key
+                }
+
+                if key == keymap.macro_record {
+                    state.count = None;
+                    if state.recording.is_some() {
+                        let message = state.toggle_recording(None);
+                        info!(target: "macro", "{}", message);
+                    } else {
+                        state.pending_register = Some(PendingRegister::Record);
+                    }
+                    return None; // Block this key
+                }
+                if is_at {
+                    state.pending_register = Some(PendingRegister::Play);
+                    return None; // Block this key
+                }
+
+                // Digits that are not bound to anything build up a count
+                let digit = typed.and_then(|c| c.to_digit(10));
+                if let Some(digit) = digit.filter(|_| !keymap.is_bound(key)) {
+                    if digit > 0 || state.count.is_some() {
+                        let count = state.count.unwrap_or(0) * 10 + digit;
+                        state.count = Some(count.min(9999));
+                        return None; // Block this key
+                    }
+                }
+            }
+
+            // Open the command line with `:` (only works in navigation mode)
+            if nav_enabled
+                && (event.name.as_deref() == Some(":")
+                    || (key == Key::SemiColon && shift_pressed))
+            {
+                let mut state = cursor_state.lock().unwrap();
+                state.command_line = Some(String::new());
+                state.count = None;
+                state.queue_mode_hooks("navigation", "command");
+                drop(state);
+                echo_command_line("");
+                return None; // Block this key
+            }
+
+            // Visual mode: while a selection holds the button, y, d, p and Esc act on it
+            // and end it, and only movement and the selection keys stay bound
+            if nav_enabled && cursor_state.lock().unwrap().selection_active {
+                let operator = if key == keymap.yank {
+                    Some(Action::Yank)
+                } else if key == keymap.cut {
+                    Some(Action::Cut)
+                } else if key == keymap.paste {
+                    Some(Action::Paste)
+                } else if key == keymap.select_cancel {
+                    Some(Action::CancelSelection)
+                } else {
+                    None
+                };
+                if let Some(action) = operator {
+                    cursor_state.lock().unwrap().count = None;
+                    run_binding(action, 1, cursor_state, navigation_enabled, &config_clone);
+                    return None; // Block this key
+                }
+                let visual_key = keymap.is_movement_key(key)
+                    || key == keymap.select_toggle
+                    || key == keymap.goto_top
+                    || key == keymap.goto_bottom;
+                let click_combination = [&keymap.double_click, &keymap.triple_click]
+                    .into_iter()
+                    .flatten()
+                    .any(|combination| {
+                        config_clone.matches_key_combination(combination, key, shift_pressed, ctrl_pressed, alt_pressed, cmd_pressed)
+                    });
+                if !visual_key && (keymap.is_bound(key) || click_combination) {
+                    cursor_state.lock().unwrap().count = None;
+                    return None; // Block this key, other bindings are off until the selection ends
+                }
+            }
+
+            // Autoclicker: a count sets the number of clicks, like 50a
+            let autoclicking = cursor_state.lock().unwrap().autoclick.is_some();
+            if nav_enabled && (key == keymap.autoclick || (autoclicking && key == keymap.autoclick_stop)) {
+                let count = cursor_state.lock().unwrap().count.take();
+                let action = if autoclicking {
+                    Action::StopAutoClick
+                } else {
+                    Action::AutoClick {
+                        button: None,
+                        interval_ms: None,
+                        clicks: count,
+                    }
+                };
+                match execute_action(&action, cursor_state, navigation_enabled, &config_clone) {
+                    Ok(message) => info!(target: "mouse", "{}", message),
+                    Err(e) => warn!(target: "mouse", "{}", e),
+                }
+                return None; // Block this key
+            }
+
+            // Discrete bindings become actions, so a count and `.` can repeat them
+            let matches_combination = |combination: Option<&KeyCombination>| {
+                combination.is_some_and(|combination| {
+                    config_clone.matches_key_combination(combination, key, shift_pressed, ctrl_pressed, alt_pressed, cmd_pressed)
+                })
+            };
+            let user_binding = keymap
+                .commands
+                .iter()
+                .find(|(combination, _)| matches_combination(Some(combination)));
+            let binding = if !nav_enabled {
+                None
+            } else if let Some((_, action)) = user_binding {
+                Some(action.clone())
+            } else if keymap.is_movement_key(key) && shift_pressed {
+                // Shift+hjkl = scroll
+                Some(Action::Scroll(keymap.scroll_direction(key).to_string()))
+            } else if keymap.is_movement_key(key) {
+                // A count turns hjkl into a hop of count * hop_step pixels, like 5l
+                let step = config_clone.hop_step;
+                let hop = match keymap.scroll_direction(key) {
+                    "left" => (-step, 0.0),
+                    "right" => (step, 0.0),
+                    "up" => (0.0, -step),
+                    _ => (0.0, step),
+                };
+                if cursor_state.lock().unwrap().count.is_some() {
+                    Some(Action::MoveBy { dx: hop.0, dy: hop.1 })
+                } else {
+                    // Normal hjkl = continuous cursor movement
+                    cursor_state.lock().unwrap().start_key_press(key);
+                    return None; // Block this key from other apps
+                }
+            } else if matches_combination(keymap.double_click.as_ref()) {
+                Some(Action::Click { button: Button::Left, clicks: 2 })
+            } else if matches_combination(keymap.triple_click.as_ref()) {
+                Some(Action::Click { button: Button::Left, clicks: 3 })
+            } else if key == keymap.click {
+                Some(Action::Click { button: Button::Left, clicks: 1 })
+            } else if key == keymap.right_click {
+                Some(Action::Click { button: Button::Right, clicks: 1 })
+            } else if key == keymap.middle_click {
+                Some(Action::Click { button: Button::Middle, clicks: 1 })
+            } else if key == keymap.back {
+                Some(Action::Click { button: BACK_BUTTON, clicks: 1 })
+            } else if key == keymap.forward {
+                Some(Action::Click { button: FORWARD_BUTTON, clicks: 1 })
+            } else if matches_combination(keymap.select_line.as_ref()) {
+                Some(Action::ToggleLineSelection)
+            } else if key == keymap.select_toggle {
+                let mut state = cursor_state.lock().unwrap();
+                if state.selection_active {
+                    Some(Action::ToggleSelection)
+                } else {
+                    // Wait for a text object before pressing the button
+                    state.count = None;
+                    state.pending_selection = Some(PendingSelection::Start);
+                    return None; // Block this key
+                }
+            } else if key == keymap.goto_top && !shift_pressed {
+                // Plain g, not Shift+G
+                Some(Action::ScreenEdge { top: true })
+            } else if key == keymap.goto_bottom && shift_pressed {
+                Some(Action::ScreenEdge { top: false })
+            } else if key == keymap.drag {
+                if cursor_state.lock().unwrap().drag.is_some() {
+                    Some(Action::EndDrag { cancel: false })
+                } else {
+                    Some(Action::StartDrag {
+                        button: keymap.drag_button,
+                        modifiers: keymap.drag_modifiers.clone(),
+                    })
+                }
+            } else if key == keymap.drag_cancel && cursor_state.lock().unwrap().drag.is_some() {
+                Some(Action::EndDrag { cancel: true })
+            } else if key == keymap.yank {
+                Some(Action::Yank)
+            } else if key == keymap.paste {
+                Some(Action::Paste)
+            } else {
+                None
+            };
+            if let Some(action) = binding {
+                let count = cursor_state.lock().unwrap().count.take();
+                run_binding(action, count.unwrap_or(1), cursor_state, navigation_enabled, &config_clone);
+                return None; // Block this key
+            }
+
+            // Escape lets go of anything still held, like a button from :press
+            if nav_enabled && key == Key::Escape && held_input_count() > 0 {
+                release_mouse_buttons(cursor_state);
+                return None; // Block this key
+            }
+
+            // Repeat the last action; a new count replaces the one it was done with
+            if nav_enabled && key == keymap.repeat {
+                let mut state = cursor_state.lock().unwrap();
+                let count = state.count.take();
+                match state.last_action.clone() {
+                    Some((action, last_count)) => {
+                        drop(state);
+                        let count = count.unwrap_or(last_count);
+                        run_binding(action, count, cursor_state, navigation_enabled, &config_clone);
+                    }
+                    None => debug!(target: "command", "Nothing to repeat"),
+                }
+                return None; // Block this key
+            }
 
-    // Initialize cursor state with config
-    let cursor_state = Arc::new(Mutex::new(CursorState::new(config.clone())?));
+            // Other keys drop a count that was typed before them
+            if !is_modifier_key(key) && key != Key::Space {
+                cursor_state.lock().unwrap().count = None;
+            }
+
+            // Block space key in navigation mode (used for precision mode)
+            if nav_enabled && key == Key::Space {
+                return None; // Block space from reaching other apps
+            }
+
+            // In navigation mode, let other keys pass through
+            // In typing mode, let all keys pass through
+            Some(event)
+        }
+        EventType::KeyRelease(key) => {
+            // Track modifier states
+            let mut state = cursor_state.lock().unwrap();
+            match key {
+                Key::ShiftLeft | Key::ShiftRight => state.shift_pressed = false,
+                Key::ControlLeft | Key::ControlRight => state.ctrl_pressed = false,
+                Key::Alt => state.alt_pressed = false,
+                Key::MetaLeft | Key::MetaRight => state.cmd_pressed = false,
+                Key::Space => state.space_pressed = false,
+                _ => {}
+            }
+            let command_line_open = state.command_line.is_some();
+            drop(state);
+
+            // Block releases of keys typed into the command line
+            if command_line_open && !is_modifier_key(key) {
+                return None;
+            }
+
+            if nav_enabled
+                && keymap.is_movement_key(key)
+            {
+                cursor_state.lock().unwrap().stop_key_press(key);
+                return None; // Block this key release too
+            }
+
+            // Block space key release in navigation mode
+            if nav_enabled && key == Key::Space {
+                return None; // Block space release from reaching other apps
+            }
+
+            Some(event)
+        }
+        _ => Some(event), // Pass through other events
+    }
+}
+
+/// Feed a journal through the event handling against a mock backend and a virtual clock.
+/// Prints the injected events, or compares them with `expect`. Returns whether
+/// everything matched the journal and the expected output.
+fn replay(
+    config_path: Option<&Path>,
+    journal_path: &Path,
+    expect: Option<&Path>,
+) -> Result<bool, VimNavError> {
+    let recorded = journal::read(journal_path).map_err(VimNavError::Command)?;
+    let config = VimNavConfig::load_quiet(config_path)?;
+
+    let started = Instant::now();
+    if VIRTUAL_CLOCK.set(Mutex::new(started)).is_err() {
+        return Err(VimNavError::Command("The clock is already in use".to_string()));
+    }
+    let set_clock = |at: Instant| *VIRTUAL_CLOCK.get().unwrap().lock().unwrap() = at;
+    let injected = Arc::new(Mutex::new(Vec::new()));
+    set_input_backend(Box::new(ReplayBackend {
+        started,
+        injected: Arc::clone(&injected),
+    }));
+
+    let screen = recorded.screen.unwrap_or((1920, 1080));
+    let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config, None, screen)));
+    let navigation_enabled = Arc::new(Mutex::new(true));
+    let mut focus = MockFocusProvider {
+        window: Arc::new(Mutex::new(None)),
+    };
+
+    let mut matched = true;
+    let mut next_tick = started;
+    for entry in &recorded.entries {
+        let at = started + Duration::from_millis(entry.at_ms());
+        // Movement ticks that would have happened before this event
+        while next_tick <= at {
+            set_clock(next_tick);
+            next_tick += Duration::from_millis(movement_tick(&cursor_state, &navigation_enabled));
+        }
+        set_clock(at);
+
+        match entry {
+            journal::Entry::Input {
+                at_ms,
+                event_type,
+                name,
+                passed,
+            } => {
+                let event = Event {
+                    time: std::time::UNIX_EPOCH + Duration::from_millis(*at_ms),
+                    name: name.clone(),
+                    event_type: *event_type,
+                };
+                let result = handle_event(event, &cursor_state, &navigation_enabled);
+                if result.is_some() != *passed {
+                    matched = false;
+                    let decision = |passed: bool| if passed { "passed" } else { "blocked" };
+                    eprintln!(
+                        "{}ms: {} was {} when recorded, {} now",
+                        at_ms,
+                        journal::format_event(event_type).replace('\t', " "),
+                        decision(*passed),
+                        decision(result.is_some())
+                    );
+                }
+            }
+            journal::Entry::Focus { window, .. } => {
+                focus.focus(window.as_ref().map(|(instance, class)| FocusedWindow {
+                    instance: instance.clone(),
+                    class: class.clone(),
+                    title: String::new(),
+                }));
+                let window = focus.focused_window();
+                apply_app_rules(&cursor_state, &navigation_enabled, window);
+            }
+            // Keys typed in typing mode pass straight through
+            journal::Entry::Redacted { .. } => {}
+        }
+        if REPLAY_QUIT.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+
+    let injected = injected.lock().unwrap();
+    let Some(expect) = expect else {
+        for line in injected.iter() {
+            println!("{}", line);
+        }
+        return Ok(matched);
+    };
+
+    let expected = std::fs::read_to_string(expect)?;
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = injected.iter().map(String::as_str).collect();
+    if expected == actual {
+        return Ok(matched);
+    }
+    let first_difference = expected
+        .iter()
+        .zip(&actual)
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or(expected.len().min(actual.len()));
+    println!("--- {}", expect.display());
+    println!("+++ replay of {}", journal_path.display());
+    println!("@@ first difference at line {} @@", first_difference + 1);
+    for line in expected.iter().skip(first_difference).take(10) {
+        println!("-{}", line);
+    }
+    for line in actual.iter().skip(first_difference).take(10) {
+        println!("+{}", line);
+    }
+    Ok(false)
+}
+
+/// Run vim navigation until the grab ends or the user quits
+fn run(config_path: Option<PathBuf>, options: &RunOptions) -> Result<(), VimNavError> {
+    // Load configuration
+    let config = VimNavConfig::load(config_path.as_deref())?;
+    if options.verbose {
+        config.print_config();
+        print_controls(&config);
+        println!("Hold movement keys longer for exponential acceleration!");
+        println!();
+    }
+    info!(target: "mode", "Vim navigation started in navigation mode");
+
+    // Initialize cursor state with config (this is the single running copy)
+    let cursor_state = Arc::new(Mutex::new(CursorState::new(config, config_path)?));
+
+    // Never leave a button or key pressed, however we exit
+    let _held_inputs = HeldInputGuard;
+    release_held_inputs_on_panic();
+    #[cfg(unix)]
+    if let Err(e) = spawn_signal_handler(Arc::clone(&cursor_state)) {
+        error!(target: "mode", "Failed to handle signals: {}", e);
+    }
+
+    // Macros recorded in earlier sessions
+    {
+        let mut state = cursor_state.lock().unwrap();
+        state.state_file = state_file_path();
+        match state.state_file.clone() {
+            Some(path) => state.macros = load_macros(&path),
+            None => warn!(target: "macro", "No state directory available, macros are not saved"),
+        }
+    }
 
     // Navigation enabled state - true = vim navigation, false = normal typing
     let navigation_enabled = Arc::new(Mutex::new(true));
 
+    // Write grabbed input to a journal with --record
+    let recorder = match &options.record {
+        Some(path) => {
+            let screen = {
+                let state = cursor_state.lock().unwrap();
+                (state.screen_width as u64, state.screen_height as u64)
+            };
+            let recorder = journal::Recorder::create(path, screen, options.record_typing)?;
+            info!(target: "input", "Recording input to {}", path.display());
+            Some(Arc::new(recorder))
+        }
+        None => None,
+    };
+
     // Move cursor to initial positionnew_speed
     move_cursor(&cursor_state)?;
-    println!("Cursor initialized at center of screen");
+    debug!(target: "movement", "Cursor initialized at center of screen");
 
     // Create a flag to control the movement thread
     let running = Arc::new(Mutex::new(true));
@@ -622,321 +5313,877 @@ fn main() -> Result<(), VimNavError> {
     let cursor_state_movement = Arc::clone(&cursor_state);
     let running_movement = Arc::clone(&running);
     let navigation_enabled_movement = Arc::clone(&navigation_enabled);
-    let config_clone = config.clone();
 
     thread::spawn(move || {
         while *running_movement.lock().unwrap() {
-            // Only move if navigation is enabled
-            if *navigation_enabled_movement.lock().unwrap() {
-                let mut state = cursor_state_movement.lock().unwrap();
-                let mut moved = false;
-
-                let left_key = config_clone
-                    .string_to_key(&config_clone.key_left)
-                    .unwrap_or(Key::KeyH);
-                let down_key = config_clone
-                    .string_to_key(&config_clone.key_down)
-                    .unwrap_or(Key::KeyJ);
-                let up_key = config_clone
-                    .string_to_key(&config_clone.key_up)
-                    .unwrap_or(Key::KeyK);
-                let right_key = config_clone
-                    .string_to_key(&config_clone.key_right)
-                    .unwrap_or(Key::KeyL);
-
-                if state.is_key_pressed(left_key) {
-                    state.move_left(left_key);
-                    moved = true;
-                }
-                if state.is_key_pressed(down_key) {
-                    state.move_down(down_key);
-                    moved = true;
-                }
-                if state.is_key_pressed(up_key) {
-                    state.move_up(up_key);
-                    moved = true;
-                }
-                if state.is_key_pressed(right_key) {
-                    state.move_right(right_key);
-                    moved = true;
-                }
-
-                if moved {
-                    drop(state); // Release the lock before calling move_cursor
-                    if let Err(e) = move_cursor(&cursor_state_movement) {
-                        eprintln!("Failed to move cursor: {:?}", e);
-                    }
-                }
-            }
-
-            thread::sleep(Duration::from_millis(config_clone.repeat_delay_ms));
+            let repeat_delay_ms =
+                movement_tick(&cursor_state_movement, &navigation_enabled_movement);
+            thread::sleep(Duration::from_millis(repeat_delay_ms));
         }
     });
 
+    // Pick up edits to the config file while running
+    spawn_config_watcher(Arc::clone(&cursor_state), Arc::clone(&running));
+
+    // Accept commands from vinavctl and other tools
+    #[cfg(unix)]
+    match spawn_control_server(Arc::clone(&cursor_state), Arc::clone(&navigation_enabled)) {
+        Ok(path) => info!(target: "control", "Control socket listening on {}", path.display()),
+        Err(e) => error!(target: "control", "Failed to start control socket: {}", e),
+    }
+
+    // Run the user's [hooks] when the mode, selection, profile or config changes
+    spawn_hook_worker();
+
+    // Adapt bindings, profile and mode to the focused application
+    spawn_focus_watcher(
+        Arc::clone(&cursor_state),
+        Arc::clone(&navigation_enabled),
+        Arc::clone(&running),
+        recorder.clone(),
+    );
+
     // Set up the event listener
     let cursor_state_clone = Arc::clone(&cursor_state);
     let navigation_enabled_clone = Arc::clone(&navigation_enabled);
-    let config_clone = config.clone();
 
     let callback = move |event: Event| -> Option<Event> {
-        let nav_enabled = *navigation_enabled_clone.lock().unwrap();
-
-        match event.event_type {
-            EventType::KeyPress(key) => {
-                // Track modifier states
-                let mut state = cursor_state_clone.lock().unwrap();
-                match key {
-                    Key::ShiftLeft | Key::ShiftRight => state.shift_pressed = true,
-                    Key::ControlLeft | Key::ControlRight => state.ctrl_pressed = true,
-                    Key::Alt => state.alt_pressed = true,
-                    Key::MetaLeft | Key::MetaRight => state.cmd_pressed = true,
-                    Key::Space => state.space_pressed = true,
-                    _ => {}
-                }
-                drop(state); // Release the lock
-
-                // Get current modifier states for key combination checking
-                let state = cursor_state_clone.lock().unwrap();
-                let shift_pressed = state.shift_pressed;
-                let ctrl_pressed = state.ctrl_pressed;
-                let alt_pressed = state.alt_pressed;
-                let cmd_pressed = state.cmd_pressed;
-                drop(state);
+        // Keys are what change the mode, selection and pending input
+        let is_key = matches!(event.event_type, EventType::KeyPress(_) | EventType::KeyRelease(_));
+        let result = match &recorder {
+            Some(recorder) => {
+                let typing = !*navigation_enabled_clone.lock().unwrap();
+                let result = handle_event(event.clone(), &cursor_state_clone, &navigation_enabled_clone);
+                recorder.record(&event, result.is_some(), typing);
+                result
+            }
+            None => handle_event(event, &cursor_state_clone, &navigation_enabled_clone),
+        };
+        if is_key {
+            notify_status_watchers();
+        }
+        result
+    };
 
-                // Mode switching - check for key combination
-                if let Some(toggle_combo) = config_clone.parse_key_combination(&config_clone.key_toggle_mode) {
-                    if config_clone.matches_key_combination(&toggle_combo, key, shift_pressed, ctrl_pressed, alt_pressed, cmd_pressed) {
-                        let mut nav_enabled_guard = navigation_enabled_clone.lock().unwrap();
-                        *nav_enabled_guard = !*nav_enabled_guard;
-                        if *nav_enabled_guard {
-                            println!("VIM NAVIGATION MODE - navigation enabled");
-                        } else {
-                            println!("TYPING MODE - navigation disabled");
-                            // Clear any pressed keys when entering typing mode
-                            cursor_state_clone.lock().unwrap().pressed_keys.clear();
-                            cursor_state_clone.lock().unwrap().current_speeds.clear();
-                        }
-                        return None; // Block this key
-                    }
-                }
+    // Start grabbing events (this will block keys from other apps)
+    let grabbed = grab(callback);
+    *running.lock().unwrap() = false;
+    release_mouse_buttons(&cursor_state);
+    run_exit_hook(&cursor_state);
 
-                // Navigation keys (only work in navigation mode)
-                if nav_enabled
-                    && (key
-                        == config_clone
-                            .string_to_key(&config_clone.key_left)
-                            .unwrap_or(Key::KeyH)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_down)
-                                .unwrap_or(Key::KeyJ)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_up)
-                                .unwrap_or(Key::KeyK)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_right)
-                                .unwrap_or(Key::KeyL))
-                {
-                    let shift_pressed = cursor_state_clone.lock().unwrap().shift_pressed;
-
-                    if shift_pressed {
-                        // Shift+hjkl = scroll
-                        let scroll_dir = match key {
-                            k if k
-                                == config_clone
-                                    .string_to_key(&config_clone.key_left)
-                                    .unwrap_or(Key::KeyH) =>
-                            {
-                                "left"
-                            }
-                            k if k
-                                == config_clone
-                                    .string_to_key(&config_clone.key_down)
-                                    .unwrap_or(Key::KeyJ) =>
-                            {
-                                "down"
-                            }
-                            k if k
-                                == config_clone
-                                    .string_to_key(&config_clone.key_up)
-                                    .unwrap_or(Key::KeyK) =>
-                            {
-                                "up"
-                            }
-                            k if k
-                                == config_clone
-                                    .string_to_key(&config_clone.key_right)
-                                    .unwrap_or(Key::KeyL) =>
-                            {
-                                "right"
-                            }
-                            _ => "",
-                        };
-                        if let Err(e) = scroll(scroll_dir, &config_clone) {
-                            eprintln!("Failed to scroll: {:?}", e);
-                        }
-                    } else {
-                        // Normal hjkl = cursor movement
-                        cursor_state_clone.lock().unwrap().start_key_press(key);
-                    }
-                    return None; // Block this key from other apps
+    match grabbed {
+        Ok(()) => {}
+        Err(error) => {
+            error!(target: "input", "Error grabbing events: {:?}", error);
+            error!(target: "input", "Note: On macOS, make sure the terminal has Accessibility permissions:");
+            error!(target: "input", "System Preferences > Security & Privacy > Privacy > Accessibility");
+            return Err(VimNavError::Grab(error));
+        }
+    }
 
-                // Mouse click (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_click)
-                            .unwrap_or(Key::Return)
-                {
-                    if let Err(e) = click_mouse(&config_clone) {
-                        eprintln!("Failed to click mouse: {:?}", e);
-                    }
-                    return None; // Block this key
+    Ok(())
+}
 
-                // Right mouse click (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_right_click)
-                            .unwrap_or(Key::KeyI)
-                {
-                    if let Err(e) = right_click_mouse(&config_clone) {
-                        eprintln!("Failed to right click mouse: {:?}", e);
-                    }
-                    return None; // Block this key
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                // Toggle text selection (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_select_toggle)
-                            .unwrap_or(Key::KeyV)
-                {
-                    if let Err(e) = toggle_selection(&cursor_state_clone) {
-                        eprintln!("Failed to toggle selection: {:?}", e);
-                    }
-                    return None; // Block this key
+    #[test]
+    fn parse_command_reads_arguments() {
+        assert_eq!(parse_command("goto 640 400"), Ok(Action::Goto { x: 640.0, y: 400.0 }));
+        assert_eq!(parse_command("  move -20   5 "), Ok(Action::MoveBy { dx: -20.0, dy: 5.0 }));
+        assert_eq!(parse_command("scroll down"), Ok(Action::Scroll("down".to_string())));
+        assert_eq!(parse_command("mode typing"), Ok(Action::SetNavigation(false)));
+        assert_eq!(parse_command("mark a"), Ok(Action::SetMark('a')));
+        assert_eq!(parse_command("profile design"), Ok(Action::Profile(Some("design".to_string()))));
+    }
 
-                // Go to top of screen (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_goto_top)
-                            .unwrap_or(Key::KeyG)
-                    && !cursor_state_clone.lock().unwrap().shift_pressed
-                // Plain G, not Shift+G
-                {
-                    if let Err(e) = goto_screen_edge(&cursor_state_clone, true) {
-                        eprintln!("Failed to go to top: {:?}", e);
-                    }
-                    return None; // Block this key
+    #[test]
+    fn parse_command_accepts_unique_prefixes() {
+        assert_eq!(parse_command("q"), Ok(Action::Quit));
+        assert_eq!(parse_command("m"), Err("Ambiguous command: m".to_string()));
+        assert_eq!(parse_command("relo"), Ok(Action::Reload));
+        assert_eq!(parse_command("rel"), Err("Ambiguous command: rel".to_string()));
+        assert_eq!(parse_command("s"), Err("Ambiguous command: s".to_string()));
+        // An exact name wins over longer names it is a prefix of
+        assert_eq!(parse_command("set"), Ok(Action::ShowSetting(None)));
+        assert_eq!(
+            parse_command("set! repeat_delay_ms = 40"),
+            Ok(Action::Set {
+                key: "repeat_delay_ms".to_string(),
+                value: "40".to_string(),
+                save: true
+            })
+        );
+    }
 
-                // Go to bottom of screen (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_goto_bottom)
-                            .unwrap_or(Key::KeyG)
-                    && cursor_state_clone.lock().unwrap().shift_pressed
-                // Shift+G
-                {
-                    if let Err(e) = goto_screen_edge(&cursor_state_clone, false) {
-                        eprintln!("Failed to go to bottom: {:?}", e);
-                    }
-                    return None; // Block this key
+    #[test]
+    fn recorded_actions_parse_back() {
+        let mut actions = vec![
+            Action::Goto { x: 640.0, y: 400.5 },
+            Action::MoveBy { dx: -20.0, dy: 5.0 },
+            Action::Scroll("left".to_string()),
+            Action::Click { button: Button::Left, clicks: 1 },
+            Action::Click { button: Button::Right, clicks: 1 },
+            Action::Click { button: Button::Left, clicks: 2 },
+            Action::PressButton(Button::Middle),
+            Action::ReleaseButton(Button::Middle),
+            Action::StartDrag { button: Button::Left, modifiers: vec![] },
+            Action::StartDrag { button: Button::Right, modifiers: vec![Key::ShiftLeft, Key::ControlLeft] },
+            Action::EndDrag { cancel: false },
+            Action::EndDrag { cancel: true },
+            Action::SetMark('a'),
+            Action::JumpToMark('z'),
+            Action::ScreenEdge { top: true },
+            Action::ScreenEdge { top: false },
+            Action::ToggleSelection,
+            Action::ToggleLineSelection,
+            Action::SelectObject(TextObject::Word),
+            Action::SelectObject(TextObject::Paragraph),
+            Action::Yank,
+            Action::Cut,
+            Action::Paste,
+            Action::CancelSelection,
+            Action::PlayMacro { register: 'q', count: 1 },
+            Action::PlayMacro { register: 'q', count: 3 },
+        ];
+        // macOS has no side buttons to send
+        if !cfg!(target_os = "macos") {
+            actions.push(Action::Click { button: BACK_BUTTON, clicks: 1 });
+        }
+        for action in actions {
+            let command = action.to_command().unwrap();
+            assert_eq!(parse_command(&command), Ok(action), ":{}", command);
+        }
+        assert_eq!(Action::Quit.to_command(), None);
+    }
 
-                // Yank/copy (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_yank)
-                            .unwrap_or(Key::KeyY)
-                {
-                    if let Err(e) = yank_copy() {
-                        eprintln!("Failed to yank/copy: {:?}", e);
-                    }
-                    return None; // Block this key
+    #[test]
+    fn parse_button_reads_names_and_numbers() {
+        assert_eq!(parse_button("left"), Ok(Button::Left));
+        assert_eq!(parse_button("Right"), Ok(Button::Right));
+        assert_eq!(parse_button("MIDDLE"), Ok(Button::Middle));
+        assert_eq!(parse_button("button12"), Ok(Button::Unknown(12)));
+        assert_eq!(parse_button("button"), Err("Unknown mouse button: button".to_string()));
+        assert_eq!(parse_button("wheel"), Err("Unknown mouse button: wheel".to_string()));
+        for name in ["back", "x1", "forward", "x2"] {
+            if cfg!(target_os = "macos") {
+                assert_eq!(parse_button(name), Err(SIDE_BUTTONS_UNSUPPORTED.to_string()));
+            } else {
+                assert!(parse_button(name).is_ok(), "{}", name);
+            }
+        }
+        if !cfg!(target_os = "macos") {
+            assert_eq!(parse_button("x1"), Ok(BACK_BUTTON));
+            assert_eq!(parse_button("forward"), Ok(FORWARD_BUTTON));
+            assert_eq!(button_name(BACK_BUTTON), "back");
+            assert_eq!(button_name(FORWARD_BUTTON), "forward");
+        }
+    }
 
-                // Paste (only works in navigation mode)
-                } else if nav_enabled
-                    && key
-                        == config_clone
-                            .string_to_key(&config_clone.key_paste)
-                            .unwrap_or(Key::KeyP)
-                {
-                    if let Err(e) = paste() {
-                        eprintln!("Failed to paste: {:?}", e);
-                    }
-                    return None; // Block this key
+    #[test]
+    fn side_buttons_are_found_in_every_button_action() {
+        for button in [BACK_BUTTON, FORWARD_BUTTON] {
+            assert!(uses_side_button(&Action::Click { button, clicks: 1 }));
+            assert!(uses_side_button(&Action::Click { button, clicks: 2 }));
+            assert!(uses_side_button(&Action::PressButton(button)));
+            assert!(uses_side_button(&Action::ReleaseButton(button)));
+            assert!(uses_side_button(&Action::StartDrag { button, modifiers: vec![] }));
+            assert!(uses_side_button(&Action::AutoClick {
+                button: Some(button),
+                interval_ms: None,
+                clicks: None
+            }));
+        }
+        assert!(!uses_side_button(&Action::Click { button: Button::Left, clicks: 1 }));
+        assert!(!uses_side_button(&Action::PressButton(Button::Middle)));
+        assert!(!uses_side_button(&Action::EndDrag { cancel: false }));
+    }
 
-                // Block space key in navigation mode (used for precision mode)
-                } else if nav_enabled && key == Key::Space {
-                    return None; // Block space from reaching other apps
-                }
+    #[test]
+    fn parse_autoclick_command_reads_button_interval_and_clicks() {
+        let autoclick = |button, interval_ms, clicks| Action::AutoClick {
+            button,
+            interval_ms,
+            clicks,
+        };
+        assert_eq!(parse_autoclick_command(&[]), Ok(autoclick(None, None, None)));
+        assert_eq!(parse_autoclick_command(&["stop"]), Ok(Action::StopAutoClick));
+        assert_eq!(
+            parse_autoclick_command(&["right"]),
+            Ok(autoclick(Some(Button::Right), None, None))
+        );
+        assert_eq!(parse_autoclick_command(&["50"]), Ok(autoclick(None, Some(50), None)));
+        assert_eq!(
+            parse_autoclick_command(&["left", "100", "20"]),
+            Ok(autoclick(Some(Button::Left), Some(100), Some(20)))
+        );
+        assert_eq!(
+            parse_autoclick_command(&["100", "99999999999"]),
+            Ok(autoclick(None, Some(100), Some(u32::MAX)))
+        );
+    }
 
-                // In navigation mode, let other keys pass through
-                // In typing mode, let all keys pass through
-                Some(event)
-            }
-            EventType::KeyRelease(key) => {
-                // Track modifier states
-                let mut state = cursor_state_clone.lock().unwrap();
-                match key {
-                    Key::ShiftLeft | Key::ShiftRight => state.shift_pressed = false,
-                    Key::ControlLeft | Key::ControlRight => state.ctrl_pressed = false,
-                    Key::Alt => state.alt_pressed = false,
-                    Key::MetaLeft | Key::MetaRight => state.cmd_pressed = false,
-                    Key::Space => state.space_pressed = false,
-                    _ => {}
-                }
-                drop(state);
+    #[test]
+    fn parse_autoclick_command_reports_errors() {
+        assert_eq!(
+            parse_autoclick_command(&["wheel"]),
+            Err("Unknown mouse button: wheel".to_string())
+        );
+        assert_eq!(parse_autoclick_command(&["0"]), Err("Invalid interval: 0".to_string()));
+        assert_eq!(
+            parse_autoclick_command(&["left", "100", "-1"]),
+            Err("Invalid number of clicks: -1".to_string())
+        );
+        assert!(parse_autoclick_command(&["left", "1", "2", "3"]).is_err());
+        assert!(parse_autoclick_command(&["stop", "now"]).is_err());
+    }
 
-                if nav_enabled
-                    && (key
-                        == config_clone
-                            .string_to_key(&config_clone.key_left)
-                            .unwrap_or(Key::KeyH)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_down)
-                                .unwrap_or(Key::KeyJ)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_up)
-                                .unwrap_or(Key::KeyK)
-                        || key
-                            == config_clone
-                                .string_to_key(&config_clone.key_right)
-                                .unwrap_or(Key::KeyL))
-                {
-                    cursor_state_clone.lock().unwrap().stop_key_press(key);
-                    return None; // Block this key release too
-                }
+    #[test]
+    fn parse_command_reports_errors_with_usage() {
+        assert_eq!(parse_command(""), Err("Empty command".to_string()));
+        assert_eq!(parse_command("frobnicate"), Err("Unknown command: frobnicate".to_string()));
+        assert_eq!(
+            parse_command("goto 1"),
+            Err("Expected two coordinates (usage: :goto <x> <y>)".to_string())
+        );
+        assert!(parse_command("mark 1").unwrap_err().starts_with("Register must be a single letter"));
+        assert!(parse_command("scroll sideways").is_err());
+    }
 
-                // Block space key release in navigation mode
-                if nav_enabled && key == Key::Space {
-                    return None; // Block space release from reaching other apps
-                }
+    fn cli(args: &[&str]) -> Result<Cli, String> {
+        Cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn cli_without_command_runs_in_foreground() {
+        let parsed = cli(&[]).unwrap();
+        assert_eq!(
+            parsed.command,
+            CliCommand::Run(RunOptions {
+                foreground: true,
+                verbose: true,
+                ..RunOptions::default()
+            })
+        );
+        assert_eq!(parsed.config_path, None);
+    }
+
+    #[test]
+    fn cli_run_flags_go_before_or_after_run() {
+        let expected = CliCommand::Run(RunOptions {
+            foreground: true,
+            dry_run: true,
+            ..RunOptions::default()
+        });
+        assert_eq!(cli(&["run", "-f", "--dry-run"]).unwrap().command, expected);
+        assert_eq!(cli(&["-f", "--dry-run", "run"]).unwrap().command, expected);
+        assert_eq!(cli(&["-v", "-v", "run"]).unwrap().log.verbosity, 2);
+    }
 
-                Some(event)
+    #[test]
+    fn cli_reads_config_path_and_subcommands() {
+        let parsed = cli(&["--config", "a.toml", "check-config"]).unwrap();
+        assert_eq!(parsed.command, CliCommand::CheckConfig);
+        assert_eq!(parsed.config_path, Some(PathBuf::from("a.toml")));
+        assert_eq!(cli(&["--config=b.toml", "dump-config"]).unwrap().config_path, Some(PathBuf::from("b.toml")));
+        assert_eq!(cli(&["--check-config"]).unwrap().command, CliCommand::CheckConfig);
+        assert_eq!(cli(&["print-keymap"]).unwrap().command, CliCommand::PrintKeymap);
+        assert_eq!(cli(&["--version"]).unwrap().command, CliCommand::Version);
+        assert_eq!(
+            cli(&["replay", "j.tsv", "--expect", "out.tsv"]).unwrap().command,
+            CliCommand::Replay {
+                journal: PathBuf::from("j.tsv"),
+                expect: Some(PathBuf::from("out.tsv")),
             }
-            _ => Some(event), // Pass through other events
+        );
+    }
+
+    #[test]
+    fn cli_rejects_bad_arguments() {
+        assert_eq!(cli(&["frobnicate"]).unwrap_err(), "Unknown command: frobnicate");
+        assert_eq!(cli(&["run", "version"]).unwrap_err(), "Unexpected argument: version");
+        assert_eq!(cli(&["--config"]).unwrap_err(), "--config needs a path");
+        assert!(cli(&["--log-level", "loud"]).is_err());
+    }
+
+    fn config_with_profile(name: &str, overrides: &str) -> VimNavConfig {
+        let mut config = VimNavConfig::default();
+        config.profiles.insert(name.to_string(), toml::from_str(overrides).unwrap());
+        config
+    }
+
+    #[test]
+    fn with_value_changes_and_clears_fields() {
+        let config = VimNavConfig::default()
+            .with_value("max_move_step", "30")
+            .unwrap();
+        assert_eq!(config.max_move_step, Some(30.0));
+        assert_eq!(config.get_value("max_move_step"), Some("30.0".to_string()));
+        let config = config.with_value("max_move_step", "none").unwrap();
+        assert_eq!(config.max_move_step, None);
+        assert_eq!(config.get_value("max_move_step"), Some("none".to_string()));
+
+        // Strings don't need quotes
+        let config = config.with_value("key_left", "a").unwrap();
+        assert_eq!(config.key_left, "a");
+
+        assert_eq!(
+            VimNavConfig::default().with_value("frobnicate", "1").unwrap_err(),
+            "Unknown option: frobnicate"
+        );
+        assert!(VimNavConfig::default().with_value("hop_step", "fast").is_err());
+    }
+
+    /// Save `value` into a config file holding `contents` and return the file afterwards
+    fn save_to(name: &str, contents: &str, key: &str, value: &str) -> String {
+        let path = std::env::temp_dir().join(format!("vinav-save-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        VimNavConfig::save_value(&path, key, value).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        saved
+    }
+
+    #[test]
+    fn save_value_only_touches_the_top_level() {
+        let contents = "\
+acceleration_multiplier = 20.0
+click_gap_ms = 20  # fast double clicks
+
+[profiles.design]
+acceleration_multiplier = 5.0
+";
+        let saved = save_to("profiles", contents, "acceleration_multiplier", "40.0");
+        assert_eq!(
+            saved,
+            "\
+acceleration_multiplier = 40.0
+click_gap_ms = 20  # fast double clicks
+
+[profiles.design]
+acceleration_multiplier = 5.0
+"
+        );
+        let saved = save_to("comment", contents, "click_gap_ms", "30");
+        assert!(saved.contains("click_gap_ms = 30  # fast double clicks\n"), "{}", saved);
+
+        // A new field goes above the tables, not into the last one
+        let saved = save_to("new", contents, "hop_step", "40.0");
+        let config: toml::Value = toml::from_str(&saved).unwrap();
+        assert_eq!(config["hop_step"].as_float(), Some(40.0));
+        assert!(config["profiles"]["design"].get("hop_step").is_none());
+    }
+
+    #[test]
+    fn save_value_removes_unset_fields() {
+        let contents = "max_move_step = 30.0\n\n[hooks]\nexit = \"true\"\n";
+        let saved = save_to("none", contents, "max_move_step", "none");
+        assert_eq!(saved, "\n[hooks]\nexit = \"true\"\n");
+        let config: VimNavConfig = toml::from_str(&saved).unwrap();
+        assert_eq!(config.max_move_step, None);
+
+        // Creates the file when there is none yet
+        let path = std::env::temp_dir().join(format!("vinav-save-created-{}", std::process::id()));
+        let file = path.join("config.toml");
+        VimNavConfig::save_value(&file, "hop_step", "40.0").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hop_step = 40.0\n");
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn validate_reports_bad_values() {
+        let config = VimNavConfig {
+            acceleration_base: 1.0,
+            acceleration_multiplier: 30.0,
+            key_left: "nokey".to_string(),
+            hop_step: -1.0,
+            click_gap_ms: 300,
+            ..VimNavConfig::default()
+        };
+        let diagnostics: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "config: error: key_left: unknown key \"nokey\"",
+                "config: error: hop_step: must not be negative",
+                "config: error: acceleration_base: must be greater than 1 when acceleration_multiplier is set \
+                 (set acceleration_multiplier = 0 for a constant speed)",
+                "config: warning: click_gap_ms: is long enough that double clicks may arrive as separate clicks",
+            ]
+        );
+        // A base of 1 is fine as long as nothing accelerates
+        let constant = VimNavConfig {
+            acceleration_base: 1.0,
+            acceleration_multiplier: 0.0,
+            ..VimNavConfig::default()
+        };
+        assert!(constant.validate().is_empty());
+        assert!(VimNavConfig::default().validate().is_empty());
+    }
+
+    #[test]
+    fn diagnostics_print_like_compiler_output() {
+        let mut diagnostic = ConfigDiagnostic::error(Some("hop_step"), "must not be negative".to_string());
+        assert_eq!(diagnostic.to_string(), "config: error: hop_step: must not be negative");
+        diagnostic.file = Some("vinav.toml".to_string());
+        diagnostic.line = Some(4);
+        assert_eq!(diagnostic.to_string(), "vinav.toml:4: error: hop_step: must not be negative");
+        let warning = ConfigDiagnostic::warning(None, "unused".to_string());
+        assert!(!warning.is_error());
+        assert_eq!(warning.to_string(), "config: warning: unused");
+    }
+
+    #[test]
+    fn find_field_line_skips_similar_names() {
+        let text = "hop_step_x = 1\n# hop_step = 2\n  hop_step=3\n[profiles.a]\nhop_step = 4\n";
+        assert_eq!(find_field_line(text, "hop_step"), Some(3));
+        assert_eq!(find_field_line(text, "key_left"), None);
+        assert_eq!(find_section_line(text, "profiles.a"), Some(4));
+        assert_eq!(line_of_offset(text, 0), 1);
+        assert_eq!(line_of_offset(text, text.find("[profiles").unwrap()), 4);
+    }
+
+    #[test]
+    fn check_file_warns_about_unknown_options() {
+        let text = "hop_stepp = 5.0\nfrobnicate = 1\n\n[profiles.design]\nkey_lef = \"a\"\n";
+        let mut diagnostics = Vec::new();
+        VimNavConfig::check_file(text, &mut diagnostics);
+        let diagnostics: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "config:2: warning: frobnicate: unknown option, it will be ignored",
+                "config:1: warning: hop_stepp: unknown option (did you mean hop_step?)",
+                "config:5: warning: key_lef: unknown option (did you mean key_left?)",
+            ]
+        );
+
+        // Type errors point at the field's line
+        let mut diagnostics = Vec::new();
+        VimNavConfig::check_file("key_left = \"h\"\nhop_step = \"far\"\n", &mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].line, Some(2));
+        assert_eq!(diagnostics[0].field.as_deref(), Some("hop_step"));
+    }
+
+    #[test]
+    fn check_config_fails_only_on_errors() {
+        let path = std::env::temp_dir().join(format!("vinav-check-{}.toml", std::process::id()));
+        std::fs::write(&path, "hop_stepp = 5.0\n").unwrap();
+        assert!(check_config(Some(&path)));
+        std::fs::write(&path, "hop_step = -5.0\n").unwrap();
+        assert!(!check_config(Some(&path)));
+        std::fs::write(&path, "hop_step = \n").unwrap();
+        assert!(!check_config(Some(&path)));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!check_config(Some(&path)));
+
+        // The example config next to the source passes
+        let example = Path::new(file!()).with_file_name(LEGACY_CONFIG_FILE);
+        assert!(check_config(Some(&example)));
+    }
+
+    #[test]
+    fn diff_lists_changed_fields() {
+        let old = VimNavConfig::default();
+        let new = VimNavConfig {
+            hop_step: 40.0,
+            key_left: "a".to_string(),
+            max_move_step: Some(30.0),
+            ..VimNavConfig::default()
+        };
+        assert_eq!(
+            old.diff(&new),
+            ["  max_move_step: none -> 30.0", "  hop_step: 20.0 -> 40.0", "  key_left: \"h\" -> \"a\""]
+        );
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn reload_keeps_the_old_config_on_errors() {
+        let path = std::env::temp_dir().join(format!("vinav-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "hop_step = 10.0\n").unwrap();
+        let config = VimNavConfig::try_load(Some(&path)).unwrap();
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config, Some(path.clone()), (1920, 1080))));
+
+        std::fs::write(&path, "hop_step = 15.0\nkey_left = \"a\"\n").unwrap();
+        assert_eq!(
+            reload_config(&cursor_state).unwrap(),
+            ["  hop_step: 10.0 -> 15.0", "  key_left: \"h\" -> \"a\""]
+        );
+        assert_eq!(cursor_state.lock().unwrap().keymap.left, Key::KeyA);
+
+        for broken in ["hop_step = -1.0\n", "hop_step = \n", "key_left = \"nokey\"\n"] {
+            std::fs::write(&path, broken).unwrap();
+            let error = reload_config(&cursor_state).unwrap_err();
+            assert!(error.starts_with("keeping the old config:\n"), "{}", error);
+            let state = cursor_state.lock().unwrap();
+            assert_eq!((state.config.hop_step, state.keymap.left), (15.0, Key::KeyA));
         }
-    };
+        std::fs::remove_file(&path).unwrap();
+        assert!(reload_config(&cursor_state).is_err());
+    }
 
-    // Start grabbing events (this will block keys from other apps)
-    match grab(callback) {
-        Ok(()) => {}
-        Err(error) => {
-            eprintln!("Error grabbing events: {:?}", error);
-            eprintln!("Note: On macOS, make sure the terminal has Accessibility permissions:");
-            eprintln!("System Preferences > Security & Privacy > Privacy > Accessibility");
-            *running.lock().unwrap() = false;
-            return Err(VimNavError::Grab(error));
+    #[test]
+    fn status_watchers_wake_on_changes() {
+        let seen = *STATUS_GENERATION.lock().unwrap();
+        let watcher = thread::spawn(move || wait_for_status_change(seen));
+        notify_status_watchers();
+        assert!(watcher.join().unwrap() > seen);
+    }
+
+    /// Wait for a hook started with run_hook to end
+    fn finish(hook: HookRun) {
+        let finished = run_hook(hook).expect("the hook starts");
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hooks_are_queued_on_the_grab_thread_and_run_later() {
+        let output = std::env::temp_dir().join(format!("vinav-hook-{}", std::process::id()));
+        let mut config = VimNavConfig::default();
+        config.hooks.mode_enter = Some(format!(
+            "printf '%s %s %s' \"$VINAV_EVENT\" \"$VINAV_MODE\" \"$VINAV_PREVIOUS_MODE\" > '{}'",
+            output.display()
+        ));
+        config.hooks.mode_exit = Some("exit 3".to_string());
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config, None, (1920, 1080))));
+        let navigation_enabled = Arc::new(Mutex::new(true));
+
+        // Cmd+Shift+S switches to typing mode, as the grab callback sees it
+        let (sender, queue) = std::sync::mpsc::channel();
+        *HOOK_QUEUE.lock().unwrap() = Some(sender);
+        for key in [Key::MetaLeft, Key::ShiftLeft, Key::KeyS] {
+            let event = Event {
+                time: std::time::SystemTime::now(),
+                name: None,
+                event_type: EventType::KeyPress(key),
+            };
+            handle_event(event, &cursor_state, &navigation_enabled);
         }
+        *HOOK_QUEUE.lock().unwrap() = None;
+        assert!(!*navigation_enabled.lock().unwrap());
+
+        let hooks: Vec<HookRun> = queue.try_iter().collect();
+        let events: Vec<&str> = hooks.iter().map(|hook| hook.event).collect();
+        assert_eq!(events, ["mode_exit", "mode_enter"]);
+        assert!(!output.exists(), "a hook ran on the grab thread");
+
+        // The failing mode_exit hook does not keep mode_enter from running
+        for hook in hooks {
+            finish(hook);
+        }
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "mode_enter typing navigation");
+        std::fs::remove_file(&output).unwrap();
     }
 
-    Ok(())
+    #[test]
+    fn hooks_get_the_state_in_their_environment() {
+        let mut config = VimNavConfig::default();
+        config.hooks.profile_switch = Some("true".to_string());
+        let mut state = CursorState::with_screen(config, None, (1920, 1080));
+        state.active_profile = Some("design".to_string());
+        state.focused_window = Some(window("Firefox", "Inbox"));
+        state.x = 100.4;
+
+        let hook = state.hook("profile_switch", vec![("VINAV_PREVIOUS_PROFILE", String::new())]).unwrap();
+        assert_eq!(hook.command, "true");
+        let env: Vec<(&str, &str)> = hook.env.iter().map(|(name, value)| (*name, value.as_str())).collect();
+        assert_eq!(
+            env,
+            [
+                ("VINAV_EVENT", "profile_switch"),
+                ("VINAV_PROFILE", "design"),
+                ("VINAV_X", "100"),
+                ("VINAV_Y", "540"),
+                ("VINAV_WINDOW", "Firefox"),
+                ("VINAV_PREVIOUS_PROFILE", ""),
+            ]
+        );
+        // Events without a command configured have no hook
+        assert!(state.hook("exit", Vec::new()).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_hook_finishes_before_returning() {
+        let output = std::env::temp_dir().join(format!("vinav-exit-hook-{}", std::process::id()));
+        let mut config = VimNavConfig::default();
+        config.hooks.exit = Some(format!("sleep 0.2; echo bye > '{}'", output.display()));
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config, None, (1920, 1080))));
+
+        // :quit and the signal handler call this right before exiting
+        run_exit_hook(&cursor_state);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "bye\n");
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_hooks_are_reported_not_fatal() {
+        for command in ["exit 3", "no-such-command-for-vinav"] {
+            finish(HookRun {
+                event: "mode_enter",
+                command: command.to_string(),
+                env: Vec::new(),
+            });
+        }
+    }
+
+    #[test]
+    fn dry_run_describes_events_with_the_cursor_position() {
+        let mut backend = DryRunBackend::new();
+        let mut at = |ms, event_type| backend.describe(Duration::from_millis(ms), &event_type);
+        assert_eq!(
+            at(0, EventType::MouseMove { x: 640.4, y: 400.6 }),
+            "+0.000s MouseMove { x: 640.4, y: 400.6 } cursor=(640, 401)"
+        );
+        // Other events keep the position of the last move
+        assert_eq!(
+            at(1_250, EventType::ButtonPress(Button::Left)),
+            "+1.250s ButtonPress(Left) cursor=(640, 401)"
+        );
+        assert_eq!(
+            at(61_005, EventType::KeyRelease(Key::MetaLeft)),
+            "+61.005s KeyRelease(MetaLeft) cursor=(640, 401)"
+        );
+    }
+
+    static INJECTED: OnceLock<Arc<Mutex<Vec<String>>>> = OnceLock::new();
+    static INJECTING: Mutex<()> = Mutex::new(());
+
+    /// Send injected events to a list instead of the OS. The guard keeps the
+    /// tests that look at the list from running at the same time.
+    fn record_injections() -> (std::sync::MutexGuard<'static, ()>, Arc<Mutex<Vec<String>>>) {
+        let guard = INJECTING.lock().unwrap_or_else(PoisonError::into_inner);
+        let injected = INJECTED.get_or_init(|| {
+            let injected = Arc::new(Mutex::new(Vec::new()));
+            set_input_backend(Box::new(ReplayBackend {
+                started: Instant::now(),
+                injected: Arc::clone(&injected),
+            }));
+            injected
+        });
+        injected.lock().unwrap().clear();
+        HELD_INPUTS.lock().unwrap().clear();
+        (guard, Arc::clone(injected))
+    }
+
+    /// Milliseconds and event of each recorded line
+    fn injected_events(injected: &Mutex<Vec<String>>) -> Vec<(u128, String)> {
+        injected
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|line| {
+                let (at_ms, event) = line.split_once('\t').unwrap();
+                (at_ms.parse().unwrap(), event.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multi_clicks_wait_the_click_gap() {
+        let (_guard, injected) = record_injections();
+        let config = VimNavConfig {
+            click_gap_ms: 40,
+            move_delay_ms: 1,
+            ..VimNavConfig::default()
+        };
+        click_mouse(Button::Left, 3, &config).unwrap();
+
+        let events = injected_events(&injected);
+        let names: Vec<&str> = events.iter().map(|(_, event)| event.as_str()).collect();
+        assert_eq!(names, ["button_press\tLeft", "button_release\tLeft"].repeat(3));
+        // Every press and release is at least click_gap_ms after the one before
+        for pair in events.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= 40, "{:?}", events);
+        }
+        assert_eq!(held_input_count(), 0);
+    }
+
+    #[test]
+    fn autoclick_stops_when_navigation_mode_is_left() {
+        let (_guard, injected) = record_injections();
+        let config = VimNavConfig {
+            click_gap_ms: 1,
+            move_delay_ms: 1,
+            ..VimNavConfig::default()
+        };
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config.clone(), None, (1920, 1080))));
+        let navigation_enabled = Arc::new(Mutex::new(true));
+        start_autoclick(&cursor_state, &navigation_enabled, Button::Left, 10, None, &config).unwrap();
+
+        let clicks = || injected_events(&injected).len() / 2;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while clicks() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(clicks() >= 2);
+        set_navigation_mode(&navigation_enabled, &cursor_state, false);
+        assert!(cursor_state.lock().unwrap().autoclick.is_none());
+
+        // A click already under way may finish, then nothing more
+        thread::sleep(Duration::from_millis(30));
+        let stopped_at = injected_events(&injected).len();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(injected_events(&injected).len(), stopped_at);
+        assert_eq!(stopped_at % 2, 0, "a button was left pressed");
+        assert!(clicks() < config.autoclick_max_clicks as usize);
+    }
+
+    #[test]
+    fn panics_on_the_grab_thread_release_held_inputs() {
+        let (_guard, injected) = record_injections();
+        let grab_thread = thread::spawn(move || {
+            release_held_inputs_on_panic();
+            inject(&EventType::KeyPress(Key::ShiftLeft)).unwrap();
+            inject(&EventType::ButtonPress(Button::Left)).unwrap();
+
+            // A worker's panic leaves them held
+            assert!(thread::spawn(|| panic!("worker")).join().is_err());
+            assert_eq!(held_input_count(), 2);
+
+            let panicked = std::panic::catch_unwind(|| panic!("grab thread"));
+            assert!(panicked.is_err());
+        });
+        let finished = grab_thread.join();
+        // Back to the default hook for the other tests
+        let _ = std::panic::take_hook();
+        finished.unwrap();
+
+        let events: Vec<String> = injected_events(&injected).into_iter().map(|(_, event)| event).collect();
+        assert_eq!(
+            events,
+            [
+                "press\tShiftLeft",
+                "button_press\tLeft",
+                "button_release\tLeft",
+                "release\tShiftLeft"
+            ]
+        );
+        assert_eq!(held_input_count(), 0);
+    }
+
+    #[test]
+    fn with_profile_overrides_fields() {
+        let config = config_with_profile("design", "precision_divisor = 5.0\nkey_click = \"space\"");
+        let design = config.with_profile("design").unwrap();
+        assert_eq!(design.precision_divisor, 5.0);
+        assert_eq!(design.key_click, "space");
+        assert_eq!(design.repeat_delay_ms, config.repeat_delay_ms);
+        // The base config is left alone
+        assert_eq!(config.precision_divisor, VimNavConfig::default().precision_divisor);
+    }
+
+    #[test]
+    fn with_profile_reports_bad_profiles() {
+        let config = config_with_profile("broken", "precision_divisor = -1.0");
+        assert_eq!(config.with_profile("missing").unwrap_err(), "Unknown profile: missing");
+        assert!(config.with_profile("broken").unwrap_err().starts_with("profiles.broken.precision_divisor:"));
+        // Unknown keys are warned about by the loader, not here
+        let config = config_with_profile("typo", "precision_divisr = 5.0");
+        assert_eq!(config.with_profile("typo").unwrap().precision_divisor, config.precision_divisor);
+    }
+
+    #[test]
+    fn next_profile_wraps_around() {
+        let mut config = config_with_profile("b", "");
+        config.profiles.insert("a".to_string(), toml::Table::new());
+        assert_eq!(config.next_profile(None).as_deref(), Some("a"));
+        assert_eq!(config.next_profile(Some("a")).as_deref(), Some("b"));
+        assert_eq!(config.next_profile(Some("b")).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn set_values_survive_profile_switches() {
+        let config = config_with_profile("design", "hop_step = 5.0");
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config.clone(), None, (1920, 1080))));
+        let navigation_enabled = Arc::new(Mutex::new(true));
+        let set = Action::Set {
+            key: "acceleration_multiplier".to_string(),
+            value: "40".to_string(),
+            save: false,
+        };
+        execute_action(&set, &cursor_state, &navigation_enabled, &config).unwrap();
+
+        let mut state = cursor_state.lock().unwrap();
+        state.switch_profile(Some("design")).unwrap();
+        assert_eq!(state.config.hop_step, 5.0);
+        assert_eq!(state.config.acceleration_multiplier, 40.0);
+        state.switch_profile(None).unwrap();
+        assert_eq!(state.config.acceleration_multiplier, 40.0);
+    }
+
+    fn window(class: &str, title: &str) -> FocusedWindow {
+        FocusedWindow {
+            instance: class.to_lowercase(),
+            class: class.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    fn app_rule(class: Option<&str>, title: Option<&str>) -> AppRule {
+        AppRule {
+            class: class.map(str::to_string),
+            title: title.map(str::to_string),
+            ..AppRule::default()
+        }
+    }
+
+    #[test]
+    fn app_rules_match_class_and_title() {
+        let rule = app_rule(Some("^(?i)firefox$"), Some("GitHub"));
+        assert!(rule.matches(&window("Firefox", "Pull requests - GitHub")));
+        assert!(!rule.matches(&window("Firefox", "Inbox")));
+        assert!(!rule.matches(&window("Chromium", "GitHub")));
+        assert!(app_rule(None, None).matches(&window("Anything", "")));
+        // Invalid patterns never match
+        assert!(!app_rule(Some("("), None).matches(&window("(", "")));
+    }
+
+    #[test]
+    fn focus_changes_apply_and_undo_app_rules() {
+        let mut config = config_with_profile("design", "precision_divisor = 5.0");
+        config.app_rules = vec![
+            AppRule {
+                profile: Some("design".to_string()),
+                keymap: toml::from_str("key_click = \"space\"").unwrap(),
+                ..app_rule(Some("Figma"), None)
+            },
+            AppRule {
+                typing: true,
+                ..app_rule(Some("Alacritty"), Some("nvim"))
+            },
+        ];
+        let cursor_state = Arc::new(Mutex::new(CursorState::with_screen(config, None, (1920, 1080))));
+        let navigation_enabled = Arc::new(Mutex::new(true));
+        let mut focus = MockFocusProvider::default();
+        let mut switch_to = |window: Option<FocusedWindow>| {
+            focus.focus(window);
+            apply_app_rules(&cursor_state, &navigation_enabled, focus.focused_window());
+            let state = cursor_state.lock().unwrap();
+            (state.active_profile.clone(), state.config.key_click.clone(), *navigation_enabled.lock().unwrap())
+        };
+
+        assert_eq!(switch_to(Some(window("Figma", "Design"))), (Some("design".to_string()), "space".to_string(), true));
+        assert_eq!(switch_to(Some(window("Alacritty", "nvim"))), (None, "return".to_string(), false));
+        assert_eq!(switch_to(Some(window("Alacritty", "bash"))), (None, "return".to_string(), true));
+        assert_eq!(switch_to(None), (None, "return".to_string(), true));
+    }
+
+    #[test]
+    fn command_names_are_unique() {
+        for (i, spec) in COMMANDS.iter().enumerate() {
+            assert!(
+                COMMANDS[i + 1..].iter().all(|other| other.name != spec.name),
+                "duplicate command {}",
+                spec.name
+            );
+            assert!(spec.usage.starts_with(spec.name), "usage of {} starts with its name", spec.name);
+        }
+    }
 }
@@ -0,0 +1,495 @@
+// Shared with the daemon, which uses the parts this client does not
+#[allow(dead_code)]
+mod control;
+
+use control::{Request, Response, Status};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+const USAGE: &str = "\
+Usage: vinavctl [--socket <path>] [--json] <COMMAND>
+
+Commands:
+  status                       Show mode, cursor position, profile and selection
+  mode <nav|typing>            Switch mode
+  goto <x> <y> [--click [button]]
+                               Move to absolute coordinates, optionally clicking there
+  move <dx> <dy>               Move relative to the current position
+  click [button] [clicks]      Click left, right, middle, back, forward or
+                               button<n>, 2 or 3 times for a double or triple click
+  press <button>               Hold a mouse button down
+  release <button>             Release a held mouse button
+  scroll <up|down|left|right>  Scroll
+  mark set <a-z>               Remember the cursor position
+  mark jump <a-z>              Move back to a mark
+  profile [name]               Switch profile, or list profiles
+  set <option>=<value>         Change a config value
+  reload                       Re-read the config file
+  quit                         Stop vim_navigation
+  cmd <command line...>        Run any `:` command
+  watch [--waybar | --format <template>]
+                               Print a line every time the mode, profile, selection
+                               pending keys, drag, autoclick or macro recording
+                               change, for status bars
+
+Watch templates can use {mode}, {label}, {profile}, {selection}, {pending},
+{recording}, {drag}, {autoclick}, {window}, {x} and {y}, e.g. --format '{label} {profile}'.
+Without an option each line is the status as JSON.
+
+--json prints the daemon's responses as JSON instead, for any command and in
+any position, e.g. vinavctl goto 100 200 --json.
+
+Exit status: 0 on success, 1 if the daemon reported an error,
+2 for usage errors, 3 if the daemon could not be reached.";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!();
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+fn parse_number(value: &str, what: &str) -> f64 {
+    value
+        .parse()
+        .unwrap_or_else(|_| usage_error(&format!("Invalid {}: {}", what, value)))
+}
+
+fn command(line: String) -> Request {
+    Request::Command { line }
+}
+
+/// Remove every --json flag, which applies to whatever command it is given with
+fn take_json_flag(args: &mut Vec<String>) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != "--json");
+    args.len() != before
+}
+
+/// Turn command line arguments into the requests to send, in order
+fn parse_requests(args: &[String]) -> Vec<Request> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["status"] => vec![Request::Status],
+        ["mode", mode] => vec![Request::SetMode {
+            mode: mode.to_string(),
+        }],
+        ["goto", x, y, rest @ ..] => {
+            let mut requests = vec![Request::MoveTo {
+                x: parse_number(x, "x coordinate"),
+                y: parse_number(y, "y coordinate"),
+            }];
+            match rest {
+                [] => {}
+                ["--click"] => requests.push(Request::Click {
+                    button: None,
+                    clicks: None,
+                }),
+                ["--click", button] => requests.push(Request::Click {
+                    button: Some(button.to_string()),
+                    clicks: None,
+                }),
+                _ => usage_error("goto takes <x> <y> [--click [button]]"),
+            }
+            requests
+        }
+        ["move", dx, dy] => vec![Request::MoveBy {
+            dx: parse_number(dx, "x offset"),
+            dy: parse_number(dy, "y offset"),
+        }],
+        ["click"] => vec![Request::Click {
+            button: None,
+            clicks: None,
+        }],
+        ["click", button] => vec![Request::Click {
+            button: Some(button.to_string()),
+            clicks: None,
+        }],
+        ["click", button, clicks] => vec![Request::Click {
+            button: Some(button.to_string()),
+            clicks: Some(
+                clicks
+                    .parse()
+                    .unwrap_or_else(|_| usage_error(&format!("Invalid number of clicks: {}", clicks))),
+            ),
+        }],
+        ["press", button] => vec![command(format!("press {}", button))],
+        ["release", button] => vec![command(format!("release {}", button))],
+        ["scroll", direction] => vec![Request::Scroll {
+            direction: direction.to_string(),
+        }],
+        ["mark", "set", name] => vec![command(format!("mark {}", name))],
+        ["mark", "jump", name] => vec![command(format!("jump {}", name))],
+        ["profile"] => vec![command("profile".to_string())],
+        ["profile", name] => vec![command(format!("profile {}", name))],
+        ["set", assignment @ ..] if !assignment.is_empty() => {
+            vec![command(format!("set {}", assignment.join(" ")))]
+        }
+        ["reload"] => vec![Request::Reload],
+        ["quit"] => vec![Request::Quit],
+        ["cmd", line @ ..] if !line.is_empty() => vec![command(line.join(" "))],
+        ["watch", ..] => vec![Request::Watch],
+        [] => usage_error("Missing command"),
+        _ => usage_error(&format!("Unknown command: {}", args.join(" "))),
+    }
+}
+
+/// How `vinavctl watch` prints each status update
+enum WatchFormat {
+    Json,
+    Waybar,
+    Template(String),
+}
+
+fn parse_watch_format(args: &[String]) -> WatchFormat {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["watch"] => WatchFormat::Json,
+        ["watch", "--waybar"] => WatchFormat::Waybar,
+        ["watch", "--format", template] => WatchFormat::Template(template.to_string()),
+        _ => usage_error("watch takes --waybar or --format <template>"),
+    }
+}
+
+/// Short mode name for a status bar, or the pending keys while there are any
+fn label(status: &Status) -> String {
+    if !status.pending.is_empty() {
+        return status.pending.clone();
+    }
+    if status.drag.is_some() {
+        return "DRAG".to_string();
+    }
+    if status.autoclick {
+        return "AUTO".to_string();
+    }
+    if status.selection {
+        return "VISUAL".to_string();
+    }
+    match status.mode.as_str() {
+        "navigation" => "NAV",
+        "typing" => "TYPE",
+        "command" => ":",
+        other => other,
+    }
+    .to_string()
+}
+
+fn fill_template(template: &str, status: &Status) -> String {
+    template
+        .replace("{mode}", &status.mode)
+        .replace("{label}", &label(status))
+        .replace("{profile}", status.profile.as_deref().unwrap_or(""))
+        .replace("{selection}", if status.selection { "selection" } else { "" })
+        .replace("{pending}", &status.pending)
+        .replace(
+            "{recording}",
+            &status.recording.as_ref().map(|register| format!("@{}", register)).unwrap_or_default(),
+        )
+        .replace("{drag}", status.drag.as_deref().unwrap_or(""))
+        .replace("{autoclick}", if status.autoclick { "autoclick" } else { "" })
+        .replace("{window}", status.window.as_deref().unwrap_or(""))
+        .replace("{x}", &format!("{:.0}", status.x))
+        .replace("{y}", &format!("{:.0}", status.y))
+}
+
+/// A line for a Waybar custom module with return-type json
+/// (https://github.com/Alexays/Waybar/wiki/Module:-Custom)
+fn waybar_line(status: &Status) -> serde_json::Value {
+    let mut class = vec![status.mode.clone()];
+    if status.selection {
+        class.push("selection".to_string());
+    }
+    if status.recording.is_some() {
+        class.push("recording".to_string());
+    }
+    if status.drag.is_some() {
+        class.push("drag".to_string());
+    }
+    if status.autoclick {
+        class.push("autoclick".to_string());
+    }
+    let tooltip = format!(
+        "mode: {}\nprofile: {}\nselection: {}",
+        status.mode,
+        status.profile.as_deref().unwrap_or("none"),
+        if status.selection { "active" } else { "off" }
+    );
+    serde_json::json!({
+        "text": label(status),
+        "alt": status.mode,
+        "class": class,
+        "tooltip": tooltip,
+    })
+}
+
+fn print_watch_line(status: &Status, format: &WatchFormat) {
+    match format {
+        WatchFormat::Json => {
+            println!("{}", serde_json::to_string(status).expect("status is valid JSON"))
+        }
+        WatchFormat::Waybar => println!("{}", waybar_line(status)),
+        WatchFormat::Template(template) => println!("{}", fill_template(template, status)),
+    }
+}
+
+fn print_response(response: &Response, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(response).expect("response is valid JSON"));
+        return;
+    }
+    if let Some(status) = &response.status {
+        println!("mode: {}", status.mode);
+        println!("cursor: {:.0} {:.0}", status.x, status.y);
+        println!("profile: {}", status.profile.as_deref().unwrap_or("none"));
+        println!("selection: {}", if status.selection { "active" } else { "off" });
+        if let Some(drag) = &status.drag {
+            println!("drag: {}", drag);
+        }
+        if status.autoclick {
+            println!("autoclick: running");
+        }
+        if let Some(register) = &status.recording {
+            println!("recording: @{}", register);
+        }
+        println!("window: {}", status.window.as_deref().unwrap_or("unknown"));
+    }
+    if let Some(message) = &response.message {
+        println!("{}", message);
+    }
+    if let Some(error) = &response.error {
+        eprintln!("{}", error);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("vinavctl needs Unix domain sockets, which this platform does not have");
+    exit(3);
+}
+
+#[cfg(unix)]
+fn main() {
+    use std::os::unix::net::UnixStream;
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut socket = control::socket_path();
+    if args.first().map(String::as_str) == Some("--socket") {
+        if args.len() < 2 {
+            usage_error("--socket needs a path");
+        }
+        socket = PathBuf::from(args.remove(1));
+        args.remove(0);
+    }
+    // Only as the command itself, so `vinavctl cmd help` still reaches the daemon
+    if matches!(args.first().map(String::as_str), Some("-h" | "--help" | "help")) {
+        println!("{}", USAGE);
+        return;
+    }
+    let json = take_json_flag(&mut args);
+    let watch_format = match args.first().map(String::as_str) {
+        Some("watch") => Some(parse_watch_format(&args)),
+        _ => None,
+    };
+    let requests = parse_requests(&args);
+
+    let stream = UnixStream::connect(&socket).unwrap_or_else(|e| {
+        eprintln!(
+            "Could not connect to {}: {} (is vim_navigation running?)",
+            socket.display(),
+            e
+        );
+        exit(3);
+    });
+    let mut writer = stream.try_clone().expect("failed to clone socket");
+    let mut reader = BufReader::new(stream);
+
+    if let Some(format) = watch_format {
+        let line = serde_json::to_string(&Request::Watch).expect("request is valid JSON");
+        if let Err(e) = writeln!(writer, "{}", line) {
+            eprintln!("Lost connection to vim_navigation: {}", e);
+            exit(3);
+        }
+        watch_status(reader, &format);
+    }
+
+    for request in requests {
+        let line = serde_json::to_string(&request).expect("request is valid JSON");
+        let mut answer = String::new();
+        let sent = writeln!(writer, "{}", line).and_then(|_| reader.read_line(&mut answer));
+        if let Err(e) = sent {
+            eprintln!("Lost connection to vim_navigation: {}", e);
+            exit(3);
+        }
+        if answer.is_empty() {
+            eprintln!("vim_navigation closed the connection");
+            exit(3);
+        }
+
+        let response: Response = serde_json::from_str(&answer).unwrap_or_else(|e| {
+            eprintln!("Invalid response from vim_navigation: {}", e);
+            exit(3);
+        });
+        print_response(&response, json);
+        if !response.ok {
+            exit(1);
+        }
+    }
+}
+
+/// Print status updates until the daemon goes away
+fn watch_status(mut reader: impl BufRead, format: &WatchFormat) -> ! {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => {
+                eprintln!("vim_navigation stopped");
+                exit(3);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Lost connection to vim_navigation: {}", e);
+                exit(3);
+            }
+        }
+        match serde_json::from_str::<Response>(&line) {
+            Ok(Response {
+                status: Some(status),
+                ..
+            }) => print_watch_line(&status, format),
+            Ok(response) => {
+                print_response(&response, false);
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("Invalid response from vim_navigation: {}", e);
+                exit(3);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parse_requests_maps_commands() {
+        assert_eq!(parse_requests(&args("mode typing")), [Request::SetMode { mode: "typing".to_string() }]);
+        assert_eq!(
+            parse_requests(&args("goto 100 200 --click")),
+            [
+                Request::MoveTo { x: 100.0, y: 200.0 },
+                Request::Click { button: None, clicks: None }
+            ]
+        );
+        assert_eq!(
+            parse_requests(&args("goto 100 200 --click right")),
+            [
+                Request::MoveTo { x: 100.0, y: 200.0 },
+                Request::Click { button: Some("right".to_string()), clicks: None }
+            ]
+        );
+        assert_eq!(
+            parse_requests(&args("mark set a")),
+            [Request::Command { line: "mark a".to_string() }]
+        );
+        assert_eq!(
+            parse_requests(&args("click middle 2")),
+            [Request::Click { button: Some("middle".to_string()), clicks: Some(2) }]
+        );
+        assert_eq!(
+            parse_requests(&args("cmd set repeat_delay_ms=20")),
+            [Request::Command { line: "set repeat_delay_ms=20".to_string() }]
+        );
+    }
+
+    fn status_in(mode: &str) -> Status {
+        Status {
+            mode: mode.to_string(),
+            x: 640.4,
+            y: 399.6,
+            profile: None,
+            selection: false,
+            pending: String::new(),
+            recording: None,
+            drag: None,
+            autoclick: false,
+            window: None,
+        }
+    }
+
+    #[test]
+    fn label_shows_pending_keys_then_the_mode() {
+        assert_eq!(label(&status_in("navigation")), "NAV");
+        assert_eq!(label(&status_in("typing")), "TYPE");
+        assert_eq!(label(&status_in("command")), ":");
+        let selecting = Status { selection: true, ..status_in("navigation") };
+        assert_eq!(label(&selecting), "VISUAL");
+        let dragging = Status { drag: Some("left".to_string()), autoclick: true, ..selecting.clone() };
+        assert_eq!(label(&dragging), "DRAG");
+        let clicking = Status { autoclick: true, ..selecting.clone() };
+        assert_eq!(label(&clicking), "AUTO");
+        let counting = Status { pending: "3".to_string(), ..dragging };
+        assert_eq!(label(&counting), "3");
+    }
+
+    #[test]
+    fn fill_template_replaces_every_field() {
+        let status = Status {
+            profile: Some("design".to_string()),
+            selection: true,
+            recording: Some("q".to_string()),
+            drag: Some("ctrl+left".to_string()),
+            window: Some("Firefox".to_string()),
+            ..status_in("navigation")
+        };
+        assert_eq!(
+            fill_template("{label} {mode} {profile} {selection} {recording} {drag} {window} {x},{y}", &status),
+            "DRAG navigation design selection @q ctrl+left Firefox 640,400"
+        );
+        // Unset fields are left empty, unknown placeholders alone
+        assert_eq!(fill_template("[{profile}{recording}{autoclick}] {other}", &status_in("typing")), "[] {other}");
+    }
+
+    #[test]
+    fn waybar_line_sets_text_and_classes() {
+        let line = waybar_line(&status_in("typing"));
+        assert_eq!(line["text"], "TYPE");
+        assert_eq!(line["alt"], "typing");
+        assert_eq!(line["class"], serde_json::json!(["typing"]));
+        assert_eq!(line["tooltip"], "mode: typing\nprofile: none\nselection: off");
+
+        let busy = Status {
+            selection: true,
+            recording: Some("a".to_string()),
+            drag: Some("left".to_string()),
+            autoclick: true,
+            ..status_in("navigation")
+        };
+        assert_eq!(
+            waybar_line(&busy)["class"],
+            serde_json::json!(["navigation", "selection", "recording", "drag", "autoclick"])
+        );
+    }
+
+    #[test]
+    fn json_flag_works_with_every_command() {
+        let mut status = args("status --json");
+        assert!(take_json_flag(&mut status));
+        assert_eq!(parse_requests(&status), [Request::Status]);
+
+        let mut goto = args("--json goto 1 2");
+        assert!(take_json_flag(&mut goto));
+        assert_eq!(parse_requests(&goto), [Request::MoveTo { x: 1.0, y: 2.0 }]);
+
+        let mut plain = args("status");
+        assert!(!take_json_flag(&mut plain));
+        assert_eq!(plain, ["status"]);
+    }
+}